@@ -1,23 +1,32 @@
 //! Everything related to placing new buildings
 
-// TODO: buildings should be gradually built, from bottom to top
 // TODO: joints should also be placeable?
 
+use std::collections::VecDeque;
+
 use avian2d::prelude::*;
 use bevy::{
     audio::Volume,
     color::palettes::css::{
-        BLACK, DARK_GRAY, DARK_GREY, DARK_SLATE_BLUE, GREEN, ORANGE, PURPLE, RED, WHITE,
-        WHITE_SMOKE,
+        BLACK, BROWN, BURLYWOOD, DARK_GRAY, DARK_GREY, DARK_SLATE_BLUE, DEEP_SKY_BLUE,
+        FOREST_GREEN, GRAY, GREEN, ORANGE, PURPLE, RED, WHITE, WHITE_SMOKE,
     },
+    input::mouse::{MouseScrollUnit, MouseWheel},
     math::NormedVectorSpace,
     prelude::*,
+    sprite::Anchor,
 };
 
 use rand::prelude::*;
 use rand::seq::SliceRandom;
 
-use crate::{inhabitants::SpawnNewInhabitant, layers::*, player::Player};
+use crate::{
+    earthquake::{EarthquakeTimer, Plate},
+    fire::OnFire,
+    inhabitants::{Inhabitant, SpawnNewInhabitant},
+    layers::*,
+    player::Player,
+};
 
 /// The kind of operations supported
 enum BuildOps {
@@ -25,6 +34,14 @@ enum BuildOps {
     Building,
     /// Build a new joint
     Joint,
+    /// Remove a building or joint for a partial refund
+    Demolish,
+    /// Put out a burning building for a fee
+    Extinguish,
+    /// Straighten and repair a building over time
+    Repair,
+    /// Put a ground floor on a base isolator pad
+    Isolate,
 }
 
 /// Saves what operation is currently selected
@@ -39,11 +56,417 @@ struct SelectedBuildOps {
 pub struct Building {
     /// size of the building
     pub size: Vec2,
+    /// what was paid for the building, used for refunds
+    pub cost: i64,
+    /// what the building is made of
+    pub material: BuildingMaterial,
+    /// what kind of building it is
+    pub variant: BuildingVariants,
+    /// goes down when the building is hit hard, it collapses at zero
+    pub health: f32,
+}
+
+/// Health of a new building
+pub const BUILDING_HEALTH: f32 = 100.0;
+
+/// Change of speed in pixels per second within a frame a building takes without damage
+const IMPACT_THRESHOLD: f32 = 10.0;
+
+/// Health lost for every pixel per second the impact is above [`IMPACT_THRESHOLD`]
+const IMPACT_DAMAGE: f32 = 1.0;
+
+/// Below this part of their health buildings start to show cracks
+const CRACK_HEALTH: f32 = 0.75;
+
+/// Number of cracks of a building at zero health
+const MAX_CRACKS: usize = 5;
+
+/// Width of a crack drawn onto a building
+const CRACK_WIDTH: f32 = 2.0;
+
+/// A crack on a damaged building, the n-th to appear
+#[derive(Component)]
+struct Crack(usize);
+
+/// A building was damaged, the inhabitants inside get hurt
+#[derive(Event)]
+pub struct BuildingDamaged {
+    /// the damaged building
+    pub building: Entity,
+    /// health lost by the building
+    pub damage: f32,
+}
+
+/// A building ran out of health and broke into debris
+#[derive(Event)]
+pub struct BuildingCollapsed;
+
+/// Number of debris pieces along the width and height of a collapsed building
+const DEBRIS_PIECES: UVec2 = UVec2::new(3, 2);
+
+/// Seconds until the debris disappears
+const DEBRIS_SECONDS: f32 = 8.0;
+
+/// Random speed the debris pieces fly apart with
+const DEBRIS_SPREAD: f32 = 80.0;
+
+/// A piece of a collapsed building
+#[derive(Component)]
+struct Debris {
+    /// despawns when finished
+    lifetime: Timer,
+}
+
+/// Height of one floor, taller buildings have more floors to rent out
+pub const FLOOR_HEIGHT: f32 = 60.0;
+
+impl Building {
+    /// rentable floor space, the width of all floors together
+    pub fn floor_space(&self) -> f32 {
+        self.size.x * self.size.y / FLOOR_HEIGHT
+    }
+
+    /// how many inhabitants fit into the building when it is a shelter
+    pub fn shelter_capacity(&self) -> usize {
+        match self.variant {
+            BuildingVariants::Basement(BasementRoom::Shelter) => {
+                (self.floor_space() / SHELTER_SPACE) as usize
+            }
+            _ => 0,
+        }
+    }
+}
+
+/// What a building is made of, decides its weight, grip, price and rent
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BuildingMaterial {
+    /// cheap and light, but the inhabitants pay less
+    Wood,
+    /// the classic
+    Brick,
+    /// heavy and expensive, but popular
+    Steel,
+    /// very heavy and grippy, the most expensive and popular
+    ReinforcedConcrete,
+}
+
+impl BuildingMaterial {
+    /// price of a building made out of this material
+    pub fn cost(&self) -> i64 {
+        match self {
+            BuildingMaterial::Wood => 300,
+            BuildingMaterial::Brick => 500,
+            BuildingMaterial::Steel => 800,
+            BuildingMaterial::ReinforcedConcrete => 1100,
+        }
+    }
+
+    /// collider density, heavier buildings are harder to shake but push harder on the ones below
+    pub fn density(&self) -> f32 {
+        match self {
+            BuildingMaterial::Wood => 0.6,
+            BuildingMaterial::Brick => 1.0,
+            BuildingMaterial::Steel => 1.6,
+            BuildingMaterial::ReinforcedConcrete => 2.4,
+        }
+    }
+
+    /// how much the building sticks to whatever it stands on
+    pub fn friction(&self) -> f32 {
+        match self {
+            BuildingMaterial::Wood => 0.5,
+            BuildingMaterial::Brick => 0.4,
+            BuildingMaterial::Steel => 0.3,
+            BuildingMaterial::ReinforcedConcrete => 0.7,
+        }
+    }
+
+    /// multiplies the rent the inhabitants pay
+    pub fn rent_multiplier(&self) -> f32 {
+        match self {
+            BuildingMaterial::Wood => 0.8,
+            BuildingMaterial::Brick => 1.0,
+            BuildingMaterial::Steel => 1.3,
+            BuildingMaterial::ReinforcedConcrete => 1.5,
+        }
+    }
+
+    /// tint of the sprite
+    pub fn tint(&self) -> Color {
+        match self {
+            BuildingMaterial::Wood => Color::srgb(1.0, 0.85, 0.65),
+            BuildingMaterial::Brick => Color::WHITE,
+            BuildingMaterial::Steel => Color::srgb(0.8, 0.85, 1.0),
+            BuildingMaterial::ReinforcedConcrete => Color::srgb(0.75, 0.75, 0.75),
+        }
+    }
+
+    /// name shown in the tool label
+    pub fn name(&self) -> &'static str {
+        match self {
+            BuildingMaterial::Wood => "Wood",
+            BuildingMaterial::Brick => "Brick",
+            BuildingMaterial::Steel => "Steel",
+            BuildingMaterial::ReinforcedConcrete => "Concrete",
+        }
+    }
+}
+
+/// Label of the build tool, shows the selected material
+#[derive(Component)]
+struct BuildToolLabel;
+
+/// A breakable joint keeping buildings together.
+/// The actual constraint is one of avian's joints on the same entity, depending on the kind.
+#[derive(Component)]
+pub struct BuildingJoint {
+    /// what kind of joint this is
+    kind: JointKind,
+    /// the constraint force at which the joint snaps
+    strength: f32,
+    /// the force the constraint currently exerts
+    force: f32,
+    /// first building
+    pub entity1: Entity,
+    /// where the joint was attached on the first building
+    local_anchor1: Vec2,
+    /// second building, or the ground / a plate if the joint is anchored
+    pub entity2: Entity,
+    /// where the joint was attached on the second building
+    local_anchor2: Vec2,
+    /// what the joint is attached to with its second end
+    anchor: JointAnchor,
+    /// what was paid for the joint, used for refunds
+    cost: i64,
+}
+
+/// What the second end of a joint is attached to, the first end is always a building
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JointAnchor {
+    /// a regular joint between two buildings
+    Building,
+    /// guy-wire to a plate, moves with the earthquake
+    Plate,
+    /// guy-wire to the static ground
+    Ground,
+    /// bolted onto a basement, holds more the deeper the basement is dug
+    Basement(f32),
+}
+
+impl JointAnchor {
+    /// finds out what was clicked, only buildings, plates and the ground can be clicked
+    fn from_entity(is_building: bool, is_plate: bool) -> Self {
+        if is_building {
+            JointAnchor::Building
+        } else if is_plate {
+            JointAnchor::Plate
+        } else {
+            JointAnchor::Ground
+        }
+    }
+
+    /// price of the anchor on top of the price of the joint
+    fn cost(&self) -> i64 {
+        match self {
+            JointAnchor::Building => 0,
+            JointAnchor::Plate => 100,
+            JointAnchor::Ground => 200,
+            JointAnchor::Basement(_) => 0,
+        }
+    }
+
+    /// anchors are dug in deep and hold more than a joint between buildings
+    fn strength_factor(&self) -> f32 {
+        match self {
+            JointAnchor::Building => 1.0,
+            JointAnchor::Plate => 1.5,
+            JointAnchor::Ground => 2.0,
+            JointAnchor::Basement(depth) => BASEMENT_ANCHOR_STRENGTH * depth,
+        }
+    }
+}
+
+/// The kinds of joints the player can choose from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JointKind {
+    /// slack rope, only pulls when it is stretched
+    Rope,
+    /// damped spring which always pulls the buildings to its rest length
+    Spring,
+    /// rigid beam which also keeps the buildings from rotating
+    Beam,
+    /// beam with a hinge in the middle, buildings can rotate around it
+    Hinge,
+}
+
+/// How much longer a rope is than the distance it was built across
+const ROPE_SLACK: f32 = 1.1;
+/// Compliance of a spring joint, the inverse of its stiffness.
+/// A brick building hanging on a spring stretches it by about 10 pixels.
+const SPRING_COMPLIANCE: f32 = 0.000_2;
+/// Linear damping of a spring joint
+const SPRING_DAMPING: f32 = 2.0;
+
+impl JointKind {
+    /// price of a joint spanning the given length
+    fn cost(&self, length: f32) -> i64 {
+        match self {
+            JointKind::Rope => (length * 2.0) as i64,
+            JointKind::Spring => (length * 3.0) as i64,
+            JointKind::Beam => 100 + (length * 6.0) as i64,
+            JointKind::Hinge => 50 + (length * 4.0) as i64,
+        }
+    }
+
+    /// constraint force a newly built joint can withstand
+    fn strength(&self) -> f32 {
+        match self {
+            JointKind::Rope => 300_000.0,
+            JointKind::Spring => 250_000.0,
+            JointKind::Beam => 900_000.0,
+            JointKind::Hinge => 600_000.0,
+        }
+    }
+
+    /// name shown in the tool label
+    fn name(&self) -> &'static str {
+        match self {
+            JointKind::Rope => "Rope",
+            JointKind::Spring => "Spring",
+            JointKind::Beam => "Beam",
+            JointKind::Hinge => "Hinge",
+        }
+    }
+}
+
+/// Gives access to the force of the different avian joints
+trait JointForce {
+    /// magnitude of the force the joint exerts
+    fn force_magnitude(&self) -> f32;
+}
+
+impl JointForce for DistanceJoint {
+    fn force_magnitude(&self) -> f32 {
+        self.force.length()
+    }
+}
+
+impl JointForce for FixedJoint {
+    fn force_magnitude(&self) -> f32 {
+        self.force.length()
+    }
+}
+
+impl JointForce for RevoluteJoint {
+    fn force_magnitude(&self) -> f32 {
+        self.force.length()
+    }
+}
+
+/// Label of the support joint tool, shows the selected joint kind
+#[derive(Component)]
+struct JointToolLabel;
+
+/// Sent when a joint was torn apart because the force on it exceeded its strength
+#[derive(Event)]
+pub struct JointBroken {
+    /// building the joint was attached to
+    pub entity1: Entity,
+    /// anchor on the first building
+    pub local_anchor1: Vec2,
+    /// second building, plate or ground the joint was attached to
+    pub entity2: Entity,
+    /// anchor on the second body
+    pub local_anchor2: Vec2,
+    /// what kind of joint it was
+    pub kind: JointKind,
+    /// what its second end was attached to
+    pub anchor: JointAnchor,
+}
+
+/// Fraction of the cost which is returned when demolishing a building or joint
+const DEMOLISH_REFUND: f32 = 0.4;
+/// How close the cursor has to be to a joint to demolish it
+const DEMOLISH_JOINT_DISTANCE: f32 = 10.0;
+
+/// Whatever would be demolished when clicking
+#[derive(Component)]
+struct DemolishPreview {
+    /// the building or joint under the cursor
+    target: Option<Entity>,
+    /// how much money the player gets back
+    refund: i64,
+}
+
+/// The remains of a joint which snapped, both ends stay attached to their buildings
+#[derive(Component)]
+struct FrayedJoint {
+    /// first building
+    entity1: Entity,
+    /// anchor on the first building
+    local_anchor1: Vec2,
+    /// second building
+    entity2: Entity,
+    /// anchor on the second building
+    local_anchor2: Vec2,
+    /// the kind it is rebuilt as when repaired
+    kind: JointKind,
+    /// what the second end is attached to
+    anchor: JointAnchor,
+}
+
+/// Money per second a repair costs
+const REPAIR_COST_PER_SECOND: i64 = 40;
+/// Health restored per second of repair
+const REPAIR_HEALTH_PER_SECOND: f32 = 10.0;
+/// How hard the jack pushes a tilted building back, relative to its inertia
+const REPAIR_STIFFNESS: f32 = 4.0;
+/// How much the jack damps the rotation, relative to its inertia
+const REPAIR_DAMPING: f32 = 4.0;
+/// A building tilted less than this counts as level
+const REPAIR_LEVEL_ANGLE: f32 = 0.02;
+
+/// A building which is being straightened and repaired
+#[derive(Component)]
+struct Repairing {
+    /// money owed for the time repaired so far, paid in whole dollars
+    owed: f32,
+}
+
+/// The building which would be repaired on click
+#[derive(Component)]
+struct RepairPreview {
+    /// building under the cursor
+    target: Option<Entity>,
+    /// whether the target is already being repaired
+    active: bool,
+    /// money per second the repair costs
+    cost: i64,
 }
 
-/// A breakable joint keeping buildings together
+/// Money per second a repair costs, a storage full of spare material makes it cheaper
+fn repair_cost(has_storage: bool) -> i64 {
+    if has_storage {
+        (REPAIR_COST_PER_SECOND as f32 * (1.0 - STORAGE_REPAIR_DISCOUNT)) as i64
+    } else {
+        REPAIR_COST_PER_SECOND
+    }
+}
+
+/// Whether one of the buildings is a storage
+fn has_storage<'a>(mut buildings: impl Iterator<Item = &'a Building>) -> bool {
+    buildings.any(|building| building.variant == BuildingVariants::Basement(BasementRoom::Storage))
+}
+
+/// Price of putting out a fire with the extinguish tool
+const EXTINGUISH_COST: i64 = 150;
+
+/// The burning building which would be put out on click
 #[derive(Component)]
-struct BuildingJoint;
+struct ExtinguishPreview {
+    /// burning building under the cursor
+    target: Option<Entity>,
+}
 
 /// A breakable joint keeping buildings together, but its preview
 #[derive(Component)]
@@ -54,20 +477,376 @@ struct BuildingJointPreview {
     local_start: Vec2,
     /// current length
     length: f32,
+    /// the kind of joint that will be built
+    kind: JointKind,
+    /// what the joint will be anchored to
+    anchor: JointAnchor,
+}
+
+/// A joint which is fully planned, but maybe not yet paid for. The building always comes first.
+#[derive(Debug, Clone, Copy)]
+struct JointPlan {
+    /// what kind of joint this is
+    kind: JointKind,
+    /// first building
+    entity1: Entity,
+    /// where the joint is attached on the first building
+    local_anchor1: Vec2,
+    /// middle of the joint relative to the first building, beams and hinges connect there
+    middle1: Vec2,
+    /// second building, or the ground / a plate
+    entity2: Entity,
+    /// where the joint is attached on the second end
+    local_anchor2: Vec2,
+    /// middle of the joint relative to the second end
+    middle2: Vec2,
+    /// length when planned
+    length: f32,
+    /// what the second end is attached to
+    anchor: JointAnchor,
+}
+
+impl JointPlan {
+    /// price of the joint including its anchor
+    fn cost(&self) -> i64 {
+        match self.anchor {
+            // bolted on for free when a building is finished on a basement, nothing to refund
+            JointAnchor::Basement(_) => 0,
+            _ => self.kind.cost(self.length) + self.anchor.cost(),
+        }
+    }
+
+    /// spawns the joint with the matching avian constraint
+    fn spawn(&self, cmd: &mut Commands) -> Entity {
+        let building_joint = BuildingJoint {
+            kind: self.kind,
+            strength: self.kind.strength() * self.anchor.strength_factor(),
+            force: 0.0,
+            entity1: self.entity1,
+            local_anchor1: self.local_anchor1,
+            entity2: self.entity2,
+            local_anchor2: self.local_anchor2,
+            anchor: self.anchor,
+            cost: self.cost(),
+        };
+
+        match self.kind {
+            JointKind::Rope => cmd.spawn((
+                building_joint,
+                DistanceJoint::new(self.entity1, self.entity2)
+                    .with_local_anchor_1(self.local_anchor1)
+                    .with_local_anchor_2(self.local_anchor2)
+                    .with_limits(0.0, self.length * ROPE_SLACK),
+            )),
+            JointKind::Spring => cmd.spawn((
+                building_joint,
+                DistanceJoint::new(self.entity1, self.entity2)
+                    .with_local_anchor_1(self.local_anchor1)
+                    .with_local_anchor_2(self.local_anchor2)
+                    .with_rest_length(self.length)
+                    .with_compliance(SPRING_COMPLIANCE)
+                    .with_linear_velocity_damping(SPRING_DAMPING),
+            )),
+            JointKind::Beam => cmd.spawn((
+                building_joint,
+                FixedJoint::new(self.entity1, self.entity2)
+                    .with_local_anchor_1(self.middle1)
+                    .with_local_anchor_2(self.middle2),
+            )),
+            JointKind::Hinge => cmd.spawn((
+                building_joint,
+                RevoluteJoint::new(self.entity1, self.entity2)
+                    .with_local_anchor_1(self.middle1)
+                    .with_local_anchor_2(self.middle2),
+            )),
+        }
+        .id()
+    }
 }
 
+/// Marks a building which is still being built from its base upwards.
+/// It pays no rent, is lighter and more slippery until it is finished.
+#[derive(Component)]
+pub struct UnderConstruction {
+    /// how far the construction has come
+    progress: Timer,
+    /// how much money was spent on this building so far
+    paid: i64,
+}
+
+/// The part of the price which has to be paid when placing the building
+const CONSTRUCTION_DOWN_PAYMENT: i64 = 100;
+/// How many seconds it takes to build a building
+const CONSTRUCTION_SECONDS: f32 = 4.0;
+/// Fraction of the already paid money which is returned when cancelling a construction
+const CONSTRUCTION_REFUND: f32 = 0.5;
+/// Collider density of buildings under construction relative to their material,
+/// makes them easier to shake off
+const CONSTRUCTION_DENSITY_FACTOR: f32 = 0.3;
+/// Friction of buildings under construction
+const CONSTRUCTION_FRICTION: f32 = 0.1;
+/// The smallest fraction of the height a building under construction has
+const CONSTRUCTION_MIN_FRACTION: f32 = 0.05;
+
 /// Types of buildings
-#[derive(Debug)]
-enum BuildingVariants {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BuildingVariants {
     /// Flat roof
     Default,
     /// building with a chimney and its offset
     Chimney(Vec2),
+    /// building with a balcony sticking out to the left (-1) or right (1), pays more rent
+    Balcony(f32),
+    /// building with a garden on its roof, neighbours pay more rent
+    GardenRoof,
+    /// heavy block without inhabitants
+    Foundation,
+    /// twice as wide, has to rest on two buildings, more inhabitants
+    Penthouse,
+    /// mechanical floor with a tuned mass damper hanging inside, reduces sway of the tower
+    Damper,
+    /// fights fires of the buildings around it
+    FireStation,
+    /// dug into the ground below the plates, hosts a special room
+    Basement(BasementRoom),
+}
+
+/// Special rooms a basement can host
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BasementRoom {
+    /// spare building material, makes repairs cheaper
+    Storage,
+    /// inhabitants of damaged buildings close by hide here until the quake is over
+    Shelter,
+}
+
+/// Strength of the anchor onto a basement per reference height of depth
+const BASEMENT_ANCHOR_STRENGTH: f32 = 1.5;
+/// Floor space a single inhabitant needs in a shelter
+const SHELTER_SPACE: f32 = 30.0;
+/// Repairs cost this much less while there is a storage
+const STORAGE_REPAIR_DISCOUNT: f32 = 0.5;
+
+/// A joint embedding a basement into a plate, or the soil at the bottom of its pit
+#[derive(Component)]
+struct BasementAnchor {
+    /// the basement held in place
+    basement: Entity,
+}
+
+/// Thickness of the soil a basement rests on
+const PIT_FLOOR_HEIGHT: f32 = 10.0;
+
+/// Mass of the damper relative to the mass of its building, heavy enough to steady the floors above
+const DAMPER_MASS_RATIO: f32 = 2.0;
+/// Radius of the damper mass
+const DAMPER_RADIUS: f32 = 12.0;
+/// Length of the damper rope relative to the height of its building
+const DAMPER_LENGTH: f32 = 0.6;
+/// Compliance of the spring the damper mass hangs on, soft enough for the mass to lag behind
+const DAMPER_COMPLIANCE: f32 = 0.000_01;
+/// Damping of the spring the damper mass hangs on, this is where the energy goes.
+/// It damps all relative motion of the mass, so too much locks it to the building.
+const DAMPER_DAMPING: f32 = 0.3;
+
+/// The mass hanging inside of a damper building
+#[derive(Component)]
+struct DamperMass {
+    /// the building the mass hangs in
+    building: Entity,
+    /// the spring joint holding the mass
+    joint: Entity,
+    /// where the spring is attached to the building
+    local_anchor: Vec2,
+}
+
+/// Hangs the damper mass into a finished damper building
+fn spawn_damper(cmd: &mut Commands, entity: Entity, building: &Building, transform: &Transform) {
+    let local_anchor = Vec2::new(0.0, building.size.y / 2.0 - 5.0);
+    let length = building.size.y * DAMPER_LENGTH;
+    let building_mass = building.material.density() * building.size.x * building.size.y;
+    let density =
+        DAMPER_MASS_RATIO * building_mass / (std::f32::consts::PI * DAMPER_RADIUS * DAMPER_RADIUS);
+
+    let mut mass_transform = *transform;
+    mass_transform.translation += transform
+        .rotation
+        .mul_vec3((local_anchor - Vec2::Y * length).extend(0.0));
+
+    let joint = cmd.spawn_empty().id();
+    let mass = cmd
+        .spawn((
+            RigidBody::Dynamic,
+            Collider::circle(DAMPER_RADIUS),
+            ColliderDensity(density),
+            // the mass swings freely inside of the building
+            CollisionLayers::NONE,
+            TransformBundle::from_transform(mass_transform),
+        ))
+        .id();
+
+    cmd.entity(joint).insert(
+        DistanceJoint::new(entity, mass)
+            .with_local_anchor_1(local_anchor)
+            .with_rest_length(length)
+            .with_compliance(DAMPER_COMPLIANCE)
+            .with_linear_velocity_damping(DAMPER_DAMPING),
+    );
+    cmd.entity(mass).insert(DamperMass {
+        building: entity,
+        joint,
+        local_anchor,
+    });
+}
+
+/// Price of putting an isolator pad under a ground floor
+const ISOLATOR_COST: i64 = 300;
+/// Height of an isolator pad, the building on it is jacked up by this much
+const ISOLATOR_HEIGHT: f32 = 12.0;
+/// Collider density of an isolator pad
+const ISOLATOR_DENSITY: f32 = 0.5;
+/// Friction between a pad and the plates, lets the plates slide away underneath
+const ISOLATOR_FRICTION: f32 = 0.02;
+/// Compliance of the rubber between the pad and its building
+const ISOLATOR_COMPLIANCE: f32 = 0.000_001;
+/// Damping of the rubber between the pad and its building, this is where the shaking goes
+const ISOLATOR_DAMPING: f32 = 6.0;
+
+/// Contacts with a normal this close to straight up lie on the top face of a building
+const TOP_FACE_NORMAL: f32 = 0.9;
+
+/// A base isolator pad a ground floor rests on instead of the plates
+#[derive(Component)]
+struct IsolatorPad {
+    /// the building resting on the pad
+    building: Entity,
+    /// the soft joint holding the building
+    joint: Entity,
+}
+
+/// The ground floor which would get an isolator pad on click
+#[derive(Component)]
+struct IsolatorPreview {
+    /// building under the cursor standing on the plates
+    target: Option<Entity>,
+}
+
+/// Size of the slab of a balcony
+const BALCONY_SIZE: Vec2 = Vec2::new(40.0, 10.0);
+/// Height of the soil on a garden roof
+const GARDEN_HEIGHT: f32 = 8.0;
+/// Buildings within this distance of a garden roof pay more rent
+pub const GARDEN_RADIUS: f32 = 160.0;
+/// Rent multiplier for buildings close to a garden roof
+pub const GARDEN_RENT_BONUS: f32 = 1.2;
+
+impl BuildingVariants {
+    /// multiplies the price of the material
+    fn cost_factor(&self) -> f32 {
+        match self {
+            BuildingVariants::Default | BuildingVariants::Chimney(_) => 1.0,
+            BuildingVariants::Balcony(_) => 1.2,
+            BuildingVariants::GardenRoof => 1.3,
+            BuildingVariants::Foundation => 0.6,
+            BuildingVariants::Penthouse => 2.0,
+            BuildingVariants::Damper => 1.5,
+            BuildingVariants::FireStation => 1.4,
+            // digging is expensive
+            BuildingVariants::Basement(_) => 1.6,
+        }
+    }
+
+    /// multiplies the density of the material
+    fn density_factor(&self) -> f32 {
+        match self {
+            BuildingVariants::Foundation => 3.0,
+            BuildingVariants::Basement(_) => 2.0,
+            _ => 1.0,
+        }
+    }
+
+    /// multiplies the rent the inhabitants pay
+    pub fn rent_multiplier(&self) -> f32 {
+        match self {
+            BuildingVariants::Balcony(_) => 1.25,
+            BuildingVariants::Penthouse => 1.5,
+            _ => 1.0,
+        }
+    }
+
+    /// name shown in the queue of upcoming buildings
+    fn name(&self) -> &'static str {
+        match self {
+            BuildingVariants::Default => "Default",
+            BuildingVariants::Chimney(_) => "Chimney",
+            BuildingVariants::Balcony(_) => "Balcony",
+            BuildingVariants::GardenRoof => "Garden",
+            BuildingVariants::Foundation => "Foundation",
+            BuildingVariants::Penthouse => "Penthouse",
+            BuildingVariants::Damper => "Damper",
+            BuildingVariants::FireStation => "Fire Station",
+            BuildingVariants::Basement(BasementRoom::Storage) => "Storage",
+            BuildingVariants::Basement(BasementRoom::Shelter) => "Shelter",
+        }
+    }
+
+    /// smallest size the player can choose, a penthouse has to span two buildings
+    fn min_size(&self) -> Vec2 {
+        match self {
+            BuildingVariants::Penthouse => Vec2::new(2.0 * REFERENCE_SIZE.x, MIN_BUILDING_SIZE.y),
+            _ => MIN_BUILDING_SIZE,
+        }
+    }
+
+    /// how many inhabitants move in once the building is finished
+    pub fn inhabitants(&self) -> usize {
+        match self {
+            BuildingVariants::Foundation
+            | BuildingVariants::Damper
+            | BuildingVariants::Basement(_) => 0,
+            BuildingVariants::Penthouse => 2,
+            _ => 1,
+        }
+    }
+
+    /// solid parts sticking out of the main body, as offset and size
+    fn parts(&self, size: Vec2) -> Vec<(Vec2, Vec2)> {
+        match self {
+            BuildingVariants::Balcony(side) => vec![(
+                Vec2::new(
+                    side * (size.x + BALCONY_SIZE.x) / 2.0,
+                    (size.y - BALCONY_SIZE.y) / 2.0,
+                ),
+                BALCONY_SIZE,
+            )],
+            BuildingVariants::GardenRoof => vec![(
+                Vec2::new(0.0, (size.y + GARDEN_HEIGHT) / 2.0),
+                Vec2::new(size.x, GARDEN_HEIGHT),
+            )],
+            _ => vec![],
+        }
+    }
+
+    /// collider of the finished building including all its parts
+    fn collider(&self, size: Vec2) -> Collider {
+        let parts = self.parts(size);
+        if parts.is_empty() {
+            return Collider::rectangle(size.x, size.y);
+        }
+
+        let mut colliders = vec![(Vec2::ZERO, 0.0, Collider::rectangle(size.x, size.y))];
+        colliders.extend(
+            parts
+                .into_iter()
+                .map(|(offset, part)| (offset, 0.0, Collider::rectangle(part.x, part.y))),
+        );
+        Collider::compound(colliders)
+    }
 }
 
 /// Chimney component
 #[derive(Component)]
-struct Chimney;
+pub struct Chimney;
 
 /// The Building which will be shown when a possible slot was found
 #[derive(Component)]
@@ -82,42 +861,362 @@ struct PreviewBuilding {
     blocked: bool,
     /// the size the building will take up
     size: Vec2,
+    /// the material the building will be made of
+    material: BuildingMaterial,
 }
 
-/// A sensor that checks that there is another building below the preview building
-#[derive(Component)]
-struct PreviewBuildingBottomSupportSensor;
+impl PreviewBuilding {
+    /// price of the building that would be built
+    fn cost(&self) -> i64 {
+        building_cost(self.material, self.variant, self.size)
+    }
+}
 
-/// Epsilon is used to inset PreviewBuilding collider checker
-const PREVIEW_BUILDING_EPS: f32 = 0.02;
+/// How many upcoming buildings are shown
+const UPCOMING_PIECES: usize = 3;
 
-/// Bundles all important components for a building
-#[derive(Bundle)]
-struct BuildingBundle {
-    /// building properties
-    building: Building,
-    /// should be static for now
-    rigidbody: RigidBody,
-    /// should be Collider::rect
-    collider: Collider,
-    /// Correct layers
-    layers: CollisionLayers,
-    /// Sprite bundle, contains transform
-    sprite: SpriteBundle,
+/// Part of the width the chimney may move away from the middle
+const CHIMNEY_RANGE: f32 = 0.4;
+
+/// A building waiting to be placed, its size is chosen by the player
+#[derive(Debug, Clone, Copy)]
+struct Piece {
+    /// the variant, the chimney offset is filled in once the size is known
+    variant: BuildingVariants,
+    /// where the chimney sits, from -1 (left) to 1 (right)
+    chimney: f32,
 }
 
-/// The component which will be attached to the cursor,
-/// then when a building wants to be placed, its sensor is checked for possible locations
+impl Piece {
+    /// rolls a new piece, plain buildings are more likely
+    fn random(rng: &mut StdRng) -> Self {
+        let variants = [
+            BuildingVariants::Default,
+            BuildingVariants::Default,
+            BuildingVariants::Default,
+            BuildingVariants::Default,
+            BuildingVariants::Chimney(Vec2::ZERO),
+            BuildingVariants::Balcony(0.0),
+            BuildingVariants::GardenRoof,
+            BuildingVariants::Foundation,
+            BuildingVariants::Penthouse,
+            BuildingVariants::Damper,
+            BuildingVariants::FireStation,
+        ];
+
+        let variant = match variants.choose(rng).unwrap() {
+            BuildingVariants::Balcony(_) => {
+                BuildingVariants::Balcony(if rng.gen_bool(0.5) { -1.0 } else { 1.0 })
+            }
+            variant => *variant,
+        };
+
+        Piece {
+            variant,
+            chimney: rng.gen_range(-1.0..=1.0),
+        }
+    }
+
+    /// the piece the preview building currently is
+    fn from_preview(preview_building: &PreviewBuilding) -> Self {
+        let chimney = match preview_building.variant {
+            BuildingVariants::Chimney(offset) => {
+                offset.x / (CHIMNEY_RANGE * preview_building.size.x)
+            }
+            _ => 0.0,
+        };
+
+        Piece {
+            variant: preview_building.variant,
+            chimney,
+        }
+    }
+
+    /// turns the preview building into this piece
+    fn apply(&self, preview_building: &mut PreviewBuilding) {
+        preview_building.size = preview_building
+            .size
+            .max(self.variant.min_size())
+            .min(MAX_BUILDING_SIZE);
+
+        preview_building.variant = match self.variant {
+            BuildingVariants::Chimney(_) => BuildingVariants::Chimney(Vec2::new(
+                self.chimney * CHIMNEY_RANGE * preview_building.size.x,
+                preview_building.size.y / 2.0 + 20.0,
+            )),
+            variant => variant,
+        };
+    }
+
+    /// short description for the queue label
+    fn label(&self) -> String {
+        let side = |x: f32| {
+            if x < -0.33 {
+                "left"
+            } else if x > 0.33 {
+                "right"
+            } else {
+                "middle"
+            }
+        };
+
+        match self.variant {
+            BuildingVariants::Chimney(_) => format!("Chimney ({})", side(self.chimney)),
+            BuildingVariants::Balcony(x) => format!("Balcony ({})", side(x)),
+            variant => variant.name().to_string(),
+        }
+    }
+}
+
+/// The upcoming buildings and the hold slot, lives next to the preview building
 #[derive(Component)]
-struct CursorBuilder;
+struct PieceQueue {
+    /// seeded, so the same seed gives the same buildings
+    rng: StdRng,
+    /// front comes next
+    upcoming: VecDeque<Piece>,
+    /// the building put aside for later
+    hold: Option<Piece>,
+    /// the hold slot can only be used once per placed building
+    swapped: bool,
+    /// the piece put aside while a basement is dug, it comes back afterwards
+    dug: Option<Piece>,
+}
 
-/// Try to place a building
-#[derive(Event)]
-struct PlaceBuildingEvent;
+impl PieceQueue {
+    /// fills the queue from the seed
+    fn new(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let upcoming = (0..UPCOMING_PIECES)
+            .map(|_| Piece::random(&mut rng))
+            .collect();
 
-/// Text that will show if player has enough money
+        PieceQueue {
+            rng,
+            upcoming,
+            hold: None,
+            swapped: false,
+            dug: None,
+        }
+    }
+
+    /// takes the next piece and rolls a new one for the end of the queue
+    fn next(&mut self) -> Piece {
+        let piece = self.upcoming.pop_front().unwrap();
+        self.upcoming.push_back(Piece::random(&mut self.rng));
+        self.swapped = false;
+
+        piece
+    }
+}
+
+/// Seed of the upcoming buildings, can be set with the `TOWER_SEED` environment variable
+fn piece_seed() -> u64 {
+    std::env::var("TOWER_SEED")
+        .ok()
+        .and_then(|seed| seed.parse().ok())
+        .unwrap_or_else(|| rand::thread_rng().gen())
+}
+
+/// Label showing the upcoming buildings and the hold slot
 #[derive(Component)]
-struct CursorBuildText;
+struct PieceQueueLabel;
+
+/// The material prices are for buildings of this size
+const REFERENCE_SIZE: Vec2 = Vec2::new(90.0, 60.0);
+
+/// Smallest building the player can choose
+const MIN_BUILDING_SIZE: Vec2 = Vec2::new(50.0, 50.0);
+
+/// Largest building the player can choose
+const MAX_BUILDING_SIZE: Vec2 = Vec2::new(240.0, 150.0);
+
+/// How much one step of the mouse wheel resizes the preview building
+const BUILDING_SIZE_STEP: f32 = 10.0;
+
+/// Pixels a touchpad has to scroll for one resize step
+const SCROLL_PIXELS_PER_STEP: f32 = 40.0;
+
+/// Part of the bottom which has to rest on something, limits how wide a building can be
+const MIN_SUPPORTED_FRACTION: f32 = 0.5;
+
+/// Number of points along the bottom which are checked for support
+const SUPPORT_SAMPLES: usize = 9;
+
+/// Price of a building, scales with its area
+fn building_cost(material: BuildingMaterial, variant: BuildingVariants, size: Vec2) -> i64 {
+    let area_factor = (size.x * size.y) / (REFERENCE_SIZE.x * REFERENCE_SIZE.y);
+    (material.cost() as f32 * variant.cost_factor() * area_factor) as i64
+}
+
+/// Height of the top of the plates, buildings resting on it need no support
+const GROUND_LEVEL: f32 = -30.0;
+
+/// Height of the area below a slot which is checked for supporting buildings
+const SUPPORT_CHECK_HEIGHT: f32 = 10.0;
+
+/// A possible slot for the preview building
+struct PreviewSlot {
+    /// center of the slot
+    position: Vec2,
+    /// something is in the way
+    blocked: bool,
+    /// enough buildings or the ground below
+    bottom_support: bool,
+}
+
+/// A building which is planned at a fixed place, but maybe not yet paid for
+#[derive(Debug, Clone, Copy)]
+struct BuildingPlan {
+    /// center of the building
+    position: Vec2,
+    /// the size the building will take up
+    size: Vec2,
+    /// the variant that will be spawned
+    variant: BuildingVariants,
+    /// the material the building will be made of
+    material: BuildingMaterial,
+}
+
+impl BuildingPlan {
+    /// price of the finished building
+    fn cost(&self) -> i64 {
+        building_cost(self.material, self.variant, self.size)
+    }
+}
+
+/// Something the player planned to build once there is enough money
+#[derive(Debug, Clone, Copy)]
+enum Blueprint {
+    /// a building at a fixed place
+    Building(BuildingPlan),
+    /// a joint between two already existing entities
+    Joint(JointPlan),
+}
+
+/// The planned buildings and joints, they are built in order
+#[derive(Resource, Default)]
+struct Blueprints {
+    /// front is built next
+    queue: VecDeque<Blueprint>,
+}
+
+/// Something the player built, so that it can be undone and redone
+#[derive(Debug, Clone)]
+struct BuildRecord {
+    /// what was built, it is built again from this on redo
+    blueprint: Blueprint,
+    /// the spawned building or joint, its inhabitants are its children
+    entity: Entity,
+    /// what was paid when it was placed, buildings under construction pay more over time
+    cost: i64,
+    /// when it was built
+    time: f32,
+    /// inhabitants who moved out when the building was undone, they move back in on redo
+    inhabitants: Vec<Entity>,
+}
+
+/// Inhabitants moving back into a redone building once it is finished
+#[derive(Component)]
+struct ReturningInhabitants(Vec<Entity>);
+
+/// The undo and redo stacks of build actions
+#[derive(Resource, Default)]
+struct BuildHistory {
+    /// last is undone next
+    undo: Vec<BuildRecord>,
+    /// last is redone next
+    redo: Vec<BuildRecord>,
+}
+
+/// Seconds after placing in which undo is always allowed and refunds everything.
+/// Later it is only allowed while there is no earthquake and refunds like demolishing.
+const UNDO_GRACE_SECONDS: f32 = 5.0;
+
+/// A building or joint was built by the player, or from a blueprint
+#[derive(Event)]
+struct BuildActionDone {
+    /// what was built
+    blueprint: Blueprint,
+    /// the spawned entity
+    entity: Entity,
+    /// what was paid
+    cost: i64,
+}
+
+/// Epsilon is used to inset PreviewBuilding collider checker
+const PREVIEW_BUILDING_EPS: f32 = 0.02;
+
+/// How much a building has to carry, from its contacts and joints
+#[derive(Component, Default)]
+struct BuildingStress {
+    /// sum of all contact and joint forces acting on the building
+    load: f32,
+    /// load relative to what the building can carry, 1 means it is about to fail
+    ratio: f32,
+    /// velocity of the building in the last frame
+    velocity: Vec2,
+    /// change of speed in the last frame which gravity doesn't explain, a steady load has none
+    impact: f32,
+}
+
+/// A building can carry this many times its own weight before it is shown as fully stressed
+const STRESS_CAPACITY: f32 = 12.0;
+
+/// Toggles the stress overlay
+#[derive(Resource, Default)]
+struct StressView {
+    /// outlines are colored by stress if true
+    enabled: bool,
+}
+
+/// Label showing if the stress view is on
+#[derive(Component)]
+struct StressViewLabel;
+
+/// Shows the stress of the building under the cursor, offset from the spot above the building
+#[derive(Component)]
+struct StressLabel(Vec3);
+
+/// Offset of the black shadow behind the white stress label
+const STRESS_LABEL_SHADOW: Vec3 = Vec3::new(-2.0, 1.0, -0.1);
+
+/// Bundles all important components for a building
+#[derive(Bundle)]
+struct BuildingBundle {
+    /// building properties
+    building: Building,
+    /// starts without any load
+    stress: BuildingStress,
+    /// should be static for now
+    rigidbody: RigidBody,
+    /// should be Collider::rect
+    collider: Collider,
+    /// Correct layers
+    layers: CollisionLayers,
+    /// depends on the material
+    density: ColliderDensity,
+    /// depends on the material
+    friction: Friction,
+    /// Sprite bundle, contains transform
+    sprite: SpriteBundle,
+}
+
+/// The component which will be attached to the cursor,
+/// then when a building wants to be placed, its sensor is checked for possible locations
+#[derive(Component)]
+struct CursorBuilder;
+
+/// Try to place a building
+#[derive(Event)]
+struct PlaceBuildingEvent {
+    /// only plan the building, it is built once it can be afforded
+    blueprint: bool,
+}
+
+/// Text that will show if player has enough money
+#[derive(Component)]
+struct CursorBuildText;
 
 /// Adds the starting building for the tower
 /// Adds the CursorBuilder
@@ -166,48 +1265,60 @@ fn add_default_entities(
         cursor_builder_layers(),
     ));
 
-    let pb = cmd
-        .spawn((
-            PreviewBuilding {
-                variant: BuildingVariants::Default,
-                visible: false,
-                bottom_support: false,
-                blocked: false,
-                size: Vec2 { x: 100.0, y: 60.0 },
-            },
-            TransformBundle::IDENTITY,
-            Sensor,
-            Collider::rectangle(100.0 - PREVIEW_BUILDING_EPS, 60.0 - PREVIEW_BUILDING_EPS),
-            preview_building_layers(),
-        ))
-        .id();
-
-    let pb_bottom_support_sensor = cmd
-        .spawn((
-            PreviewBuildingBottomSupportSensor,
-            TransformBundle::from_transform(Transform::from_xyz(0.0, -40.0, 0.0)),
-            Sensor,
-            Collider::rectangle(90.0, 10.0),
-            preview_building_layers(), // NOTE: assumptions that same layers dont automatically collide
-        ))
-        .id();
-
-    cmd.entity(pb).add_child(pb_bottom_support_sensor);
+    cmd.spawn((
+        PreviewBuilding {
+            variant: BuildingVariants::Default,
+            visible: false,
+            bottom_support: false,
+            blocked: false,
+            size: Vec2 { x: 100.0, y: 60.0 },
+            material: BuildingMaterial::Brick,
+        },
+        PieceQueue::new(piece_seed()),
+        TransformBundle::IDENTITY,
+        Sensor,
+        Collider::rectangle(100.0 - PREVIEW_BUILDING_EPS, 60.0 - PREVIEW_BUILDING_EPS),
+        preview_building_layers(),
+    ));
 
     cmd.spawn(BuildingJointPreview {
         entity_start: None,
         local_start: Vec2::ZERO,
         length: 0.0,
+        kind: JointKind::Rope,
+        anchor: JointAnchor::Building,
+    });
+
+    cmd.spawn(DemolishPreview {
+        target: None,
+        refund: 0,
+    });
+
+    cmd.spawn(ExtinguishPreview { target: None });
+
+    cmd.spawn(IsolatorPreview { target: None });
+
+    cmd.spawn(RepairPreview {
+        target: None,
+        active: false,
+        cost: REPAIR_COST_PER_SECOND,
     });
 
     let building = cmd
         .spawn(BuildingBundle {
             building: Building {
                 size: Vec2::new(100.0, 60.0),
+                cost: 0,
+                material: BuildingMaterial::Brick,
+                variant: BuildingVariants::Default,
+                health: BUILDING_HEALTH,
             },
+            stress: BuildingStress::default(),
             rigidbody: RigidBody::Dynamic,
             collider: Collider::rectangle(100.0, 60.0),
             layers: building_layers(),
+            density: ColliderDensity(BuildingMaterial::Brick.density()),
+            friction: Friction::new(BuildingMaterial::Brick.friction()),
             sprite: SpriteBundle {
                 texture: asset_server.load("apartmentPINK.png"),
                 sprite: Sprite {
@@ -221,8 +1332,180 @@ fn add_default_entities(
 
     inhabitants.send(SpawnNewInhabitant(building));
 
+    cmd.spawn((
+        StressLabel(STRESS_LABEL_SHADOW),
+        Text2dBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/RobotoSlab.ttf"),
+                    font_size: 32.0,
+                    color: BLACK.into(),
+                },
+            ),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+    ));
+
+    cmd.spawn((
+        StressLabel(Vec3::ZERO),
+        Text2dBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/RobotoSlab.ttf"),
+                    font_size: 30.0,
+                    color: WHITE.into(),
+                },
+            ),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+    ));
+
+    cmd.spawn((
+        StressViewLabel,
+        TextBundle::from_section(
+            "Stress View (V)",
+            TextStyle {
+                font: asset_server.load("fonts/RobotoSlab.ttf"),
+                font_size: 42.0,
+                color: BLACK.into(),
+            },
+        )
+        .with_text_justify(JustifyText::Center)
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(205.0),
+            left: Val::Px(3.0),
+            ..default()
+        }),
+    ));
+
+    cmd.spawn((
+        StressViewLabel,
+        TextBundle::from_section(
+            "Stress View (V)",
+            TextStyle {
+                font: asset_server.load("fonts/RobotoSlab.ttf"),
+                font_size: 40.0,
+                color: WHITE.into(),
+            },
+        )
+        .with_text_justify(JustifyText::Center)
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(205.0),
+            left: Val::Px(5.0),
+            ..default()
+        }),
+    ));
+
+    cmd.spawn((
+        PieceQueueLabel,
+        TextBundle::from_section(
+            "Next:",
+            TextStyle {
+                font: asset_server.load("fonts/RobotoSlab.ttf"),
+                font_size: 42.0,
+                color: BLACK.into(),
+            },
+        )
+        .with_text_justify(JustifyText::Center)
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(160.0),
+            left: Val::Px(3.0),
+            ..default()
+        }),
+    ));
+
+    cmd.spawn((
+        PieceQueueLabel,
+        TextBundle::from_section(
+            "Next:",
+            TextStyle {
+                font: asset_server.load("fonts/RobotoSlab.ttf"),
+                font_size: 40.0,
+                color: WHITE.into(),
+            },
+        )
+        .with_text_justify(JustifyText::Center)
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(160.0),
+            left: Val::Px(5.0),
+            ..default()
+        }),
+    ));
+
+    cmd.spawn((TextBundle::from_section(
+        format!("Isolator Tool (I): {}$", ISOLATOR_COST),
+        TextStyle {
+            font: asset_server.load("fonts/RobotoSlab.ttf"),
+            font_size: 42.0,
+            color: BLACK.into(),
+        },
+    )
+    .with_text_justify(JustifyText::Center)
+    .with_style(Style {
+        position_type: PositionType::Absolute,
+        bottom: Val::Px(340.0),
+        left: Val::Px(3.0),
+        ..default()
+    }),));
+
+    cmd.spawn((TextBundle::from_section(
+        format!("Isolator Tool (I): {}$", ISOLATOR_COST),
+        TextStyle {
+            font: asset_server.load("fonts/RobotoSlab.ttf"),
+            font_size: 40.0,
+            color: WHITE.into(),
+        },
+    )
+    .with_text_justify(JustifyText::Center)
+    .with_style(Style {
+        position_type: PositionType::Absolute,
+        bottom: Val::Px(340.0),
+        left: Val::Px(5.0),
+        ..default()
+    }),));
+
+    cmd.spawn((TextBundle::from_section(
+        format!("Repair Tool (R): {}$/s", REPAIR_COST_PER_SECOND),
+        TextStyle {
+            font: asset_server.load("fonts/RobotoSlab.ttf"),
+            font_size: 42.0,
+            color: BLACK.into(),
+        },
+    )
+    .with_text_justify(JustifyText::Center)
+    .with_style(Style {
+        position_type: PositionType::Absolute,
+        bottom: Val::Px(295.0),
+        left: Val::Px(3.0),
+        ..default()
+    }),));
+
+    cmd.spawn((TextBundle::from_section(
+        format!("Repair Tool (R): {}$/s", REPAIR_COST_PER_SECOND),
+        TextStyle {
+            font: asset_server.load("fonts/RobotoSlab.ttf"),
+            font_size: 40.0,
+            color: WHITE.into(),
+        },
+    )
+    .with_text_justify(JustifyText::Center)
+    .with_style(Style {
+        position_type: PositionType::Absolute,
+        bottom: Val::Px(295.0),
+        left: Val::Px(5.0),
+        ..default()
+    }),));
+
     cmd.spawn((TextBundle::from_section(
-        "Build Tool (B)",
+        format!("Extinguish Tool (F): {}$", EXTINGUISH_COST),
         TextStyle {
             font: asset_server.load("fonts/RobotoSlab.ttf"),
             font_size: 42.0,
@@ -232,13 +1515,13 @@ fn add_default_entities(
     .with_text_justify(JustifyText::Center)
     .with_style(Style {
         position_type: PositionType::Absolute,
-        bottom: Val::Px(70.0),
+        bottom: Val::Px(250.0),
         left: Val::Px(3.0),
         ..default()
     }),));
 
     cmd.spawn((TextBundle::from_section(
-        "Build Tool (B)",
+        format!("Extinguish Tool (F): {}$", EXTINGUISH_COST),
         TextStyle {
             font: asset_server.load("fonts/RobotoSlab.ttf"),
             font_size: 40.0,
@@ -248,13 +1531,13 @@ fn add_default_entities(
     .with_text_justify(JustifyText::Center)
     .with_style(Style {
         position_type: PositionType::Absolute,
-        bottom: Val::Px(70.0),
+        bottom: Val::Px(250.0),
         left: Val::Px(5.0),
         ..default()
     }),));
 
     cmd.spawn((TextBundle::from_section(
-        "Support Joint Tool (J)",
+        "Demolish Tool (D)",
         TextStyle {
             font: asset_server.load("fonts/RobotoSlab.ttf"),
             font_size: 42.0,
@@ -264,13 +1547,13 @@ fn add_default_entities(
     .with_text_justify(JustifyText::Center)
     .with_style(Style {
         position_type: PositionType::Absolute,
-        bottom: Val::Px(25.0),
-        left: Val::Px(1.0),
+        bottom: Val::Px(115.0),
+        left: Val::Px(3.0),
         ..default()
     }),));
 
     cmd.spawn((TextBundle::from_section(
-        "Support Joint Tool (J)",
+        "Demolish Tool (D)",
         TextStyle {
             font: asset_server.load("fonts/RobotoSlab.ttf"),
             font_size: 40.0,
@@ -280,10 +1563,86 @@ fn add_default_entities(
     .with_text_justify(JustifyText::Center)
     .with_style(Style {
         position_type: PositionType::Absolute,
-        bottom: Val::Px(25.0),
+        bottom: Val::Px(115.0),
         left: Val::Px(5.0),
         ..default()
     }),));
+
+    cmd.spawn((
+        BuildToolLabel,
+        TextBundle::from_section(
+            "Build Tool (B)",
+            TextStyle {
+                font: asset_server.load("fonts/RobotoSlab.ttf"),
+                font_size: 42.0,
+                color: BLACK.into(),
+            },
+        )
+        .with_text_justify(JustifyText::Center)
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(70.0),
+            left: Val::Px(3.0),
+            ..default()
+        }),
+    ));
+
+    cmd.spawn((
+        BuildToolLabel,
+        TextBundle::from_section(
+            "Build Tool (B)",
+            TextStyle {
+                font: asset_server.load("fonts/RobotoSlab.ttf"),
+                font_size: 40.0,
+                color: WHITE.into(),
+            },
+        )
+        .with_text_justify(JustifyText::Center)
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(70.0),
+            left: Val::Px(5.0),
+            ..default()
+        }),
+    ));
+
+    cmd.spawn((
+        JointToolLabel,
+        TextBundle::from_section(
+            "Support Joint Tool (J)",
+            TextStyle {
+                font: asset_server.load("fonts/RobotoSlab.ttf"),
+                font_size: 42.0,
+                color: BLACK.into(),
+            },
+        )
+        .with_text_justify(JustifyText::Center)
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(25.0),
+            left: Val::Px(1.0),
+            ..default()
+        }),
+    ));
+
+    cmd.spawn((
+        JointToolLabel,
+        TextBundle::from_section(
+            "Support Joint Tool (J)",
+            TextStyle {
+                font: asset_server.load("fonts/RobotoSlab.ttf"),
+                font_size: 40.0,
+                color: WHITE.into(),
+            },
+        )
+        .with_text_justify(JustifyText::Center)
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(25.0),
+            left: Val::Px(5.0),
+            ..default()
+        }),
+    ));
 }
 
 /// Sets the position of the CursorBuilder to the cursors position
@@ -314,12 +1673,22 @@ fn update_cursor_builder(
 /// update how much money a building costs
 fn update_cursor_text(
     mut texts: Query<(&mut Transform, &mut Text), With<CursorBuildText>>,
-    windows: Query<&Window>,
-    cameras: Query<(&Camera, &GlobalTransform)>,
+    (windows, cameras): (Query<&Window>, Query<(&Camera, &GlobalTransform)>),
     player: Res<Player>,
     ops: Res<SelectedBuildOps>,
-    joint_preview: Query<&BuildingJointPreview>,
+    keys: Res<ButtonInput<KeyCode>>,
+    previews: (
+        Query<&PreviewBuilding>,
+        Query<&BuildingJointPreview>,
+        Query<&DemolishPreview>,
+        Query<&ExtinguishPreview>,
+    ),
+    tool_previews: (Query<&RepairPreview>, Query<&IsolatorPreview>),
 ) {
+    let (building_preview, joint_preview, demolish_preview, extinguish_preview) = previews;
+    let (repair_preview, isolator_preview) = tool_previews;
+    let planning = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+
     let window = windows.single();
     let (camera, camera_transform) = cameras.single();
 
@@ -337,106 +1706,252 @@ fn update_cursor_text(
     for (mut transform, mut text) in texts.iter_mut() {
         transform.translation = (cursor_world_position + Vec2::new(60.0, -40.0)).extend(10.0);
 
-        if matches!(ops.selected, BuildOps::Building) {
-            if player.money < 500 {
-                text.sections[0].value = "Requires 500$".to_string();
-            } else {
-                text.sections[0].value = "".to_string();
+        match ops.selected {
+            BuildOps::Building => {
+                let material = building_preview.single().material;
+
+                if planning {
+                    text.sections[0].value = format!(
+                        "Plan {} {}$",
+                        material.name(),
+                        building_preview.single().cost()
+                    );
+                } else if player.money < CONSTRUCTION_DOWN_PAYMENT {
+                    text.sections[0].value = format!("Requires {}$", CONSTRUCTION_DOWN_PAYMENT);
+                } else {
+                    text.sections[0].value =
+                        format!("{} {}$", material.name(), building_preview.single().cost());
+                }
             }
-        } else {
-            let preview = joint_preview.single();
+            BuildOps::Joint => {
+                let preview = joint_preview.single();
 
-            if preview.length < 0.1 {
-                text.sections[0].value = "".to_string();
-            } else if player.money < preview.length as i64 * 3 {
-                text.sections[0].value = format!("Requires {}$", preview.length as i32 * 3);
-            } else {
-                text.sections[0].value = format!("{}$", preview.length as i32 * 3);
+                if preview.length < 0.1 {
+                    text.sections[0].value = "".to_string();
+                } else {
+                    let cost = preview.kind.cost(preview.length) + preview.anchor.cost();
+                    if planning {
+                        text.sections[0].value = format!("Plan {}$", cost);
+                    } else if player.money < cost {
+                        text.sections[0].value = format!("Requires {}$", cost);
+                    } else {
+                        text.sections[0].value = format!("{}$", cost);
+                    }
+                }
+            }
+            BuildOps::Demolish => {
+                let preview = demolish_preview.single();
+
+                if preview.target.is_none() {
+                    text.sections[0].value = "".to_string();
+                } else {
+                    text.sections[0].value = format!("+{}$", preview.refund);
+                }
+            }
+            BuildOps::Extinguish => {
+                let preview = extinguish_preview.single();
+
+                if preview.target.is_none() {
+                    text.sections[0].value = "".to_string();
+                } else if player.money < EXTINGUISH_COST {
+                    text.sections[0].value = format!("Requires {}$", EXTINGUISH_COST);
+                } else {
+                    text.sections[0].value = format!("-{}$", EXTINGUISH_COST);
+                }
+            }
+            BuildOps::Repair => {
+                let preview = repair_preview.single();
+
+                if preview.target.is_none() {
+                    text.sections[0].value = "".to_string();
+                } else if preview.active {
+                    text.sections[0].value = "Repairing".to_string();
+                } else if player.money < preview.cost {
+                    text.sections[0].value = format!("Requires {}$", preview.cost);
+                } else {
+                    text.sections[0].value = format!("-{}$/s", preview.cost);
+                }
+            }
+            BuildOps::Isolate => {
+                let preview = isolator_preview.single();
+
+                if preview.target.is_none() {
+                    text.sections[0].value = "".to_string();
+                } else if player.money < ISOLATOR_COST {
+                    text.sections[0].value = format!("Requires {}$", ISOLATOR_COST);
+                } else {
+                    text.sections[0].value = format!("-{}$", ISOLATOR_COST);
+                }
             }
         }
     }
 }
 
-/// Checks if the event was called in a place where there is a possible slot nearby
+/// Looks for possible slots around the buildings close to the cursor: beside any floor,
+/// on top, underneath overhangs and on the ground. Picks the closest valid one.
 fn update_preview_building(
     builders: Query<(&GlobalTransform, &CollidingEntities), With<CursorBuilder>>,
-    mut preview_buildings: Query<(&mut PreviewBuilding, &mut Transform, &CollidingEntities)>,
-    bottom_support_sensors: Query<&CollidingEntities, With<PreviewBuildingBottomSupportSensor>>,
-    buildings: Query<(&GlobalTransform, &Collider, &ColliderAabb), With<Building>>,
+    mut preview_buildings: Query<(&mut PreviewBuilding, &mut Transform, &Collider)>,
+    buildings: Query<(&Building, &GlobalTransform, &ColliderAabb)>,
+    spatial_query: SpatialQuery,
 ) {
     if builders.is_empty() || preview_buildings.is_empty() {
         return;
     }
 
     let (builder_transform, builder_collisions) = builders.single();
-    let (mut preview_building, mut pb_transform, pb_colliding_entities) =
-        preview_buildings.single_mut();
+    let (mut preview_building, mut pb_transform, pb_collider) = preview_buildings.single_mut();
 
     let builder_pos = builder_transform.translation().xy();
+    let size = preview_building.size;
+    let basement = matches!(preview_building.variant, BuildingVariants::Basement(_));
 
-    let closest_building =
-        match find_building_closest_to_cursor(builder_collisions, &buildings, builder_transform) {
-            Some(x) => x,
-            None => {
-                preview_building.visible = false;
-                pb_transform.translation = builder_pos.extend(0.0);
-                return;
-            }
+    let mut positions = vec![];
+    for entity in builder_collisions.iter() {
+        let (building, building_transform, building_aabb) = match buildings.get(*entity) {
+            Ok(x) => x,
+            Err(_) => continue,
         };
 
-    let (closest_building_transform, _, closest_building_aabb) =
-        buildings.get(closest_building).unwrap();
-
-    if builder_pos.y >= closest_building_aabb.min.y && builder_pos.y <= closest_building_aabb.max.y
-    {
-        preview_building.visible = true;
-
-        // same level
-        if builder_pos.x < closest_building_transform.translation().x {
-            // left
-            pb_transform.translation = Vec2::new(
-                closest_building_aabb.min.x - preview_building.size.x / 2.0,
-                closest_building_transform.translation().y,
-            )
-            .extend(0.0);
-        } else {
-            // right
-            pb_transform.translation = Vec2::new(
-                closest_building_aabb.max.x + preview_building.size.x / 2.0,
-                closest_building_transform.translation().y,
-            )
-            .extend(0.0);
+        // basements are dug right below the ground, next to each other
+        if basement {
+            if matches!(building.variant, BuildingVariants::Basement(_)) {
+                let row = GROUND_LEVEL - size.y / 2.0;
+                positions.push(Vec2::new(building_aabb.min.x - size.x / 2.0, row));
+                positions.push(Vec2::new(building_aabb.max.x + size.x / 2.0, row));
+            }
+            continue;
         }
-    } else if builder_pos.y >= closest_building_aabb.max.y {
-        preview_building.visible = true;
-        pb_transform.translation = Vec2::new(
-            builder_transform.translation().x,
-            closest_building_aabb.max.y + preview_building.size.y / 2.0,
-        )
-        .extend(0.0);
-    } else {
+
+        // neighbours stand on the same level
+        let row = building_transform.translation().y - building.size.y / 2.0 + size.y / 2.0;
+
+        positions.push(Vec2::new(building_aabb.min.x - size.x / 2.0, row));
+        positions.push(Vec2::new(building_aabb.max.x + size.x / 2.0, row));
+        positions.push(Vec2::new(builder_pos.x, building_aabb.max.y + size.y / 2.0));
+        positions.push(Vec2::new(builder_pos.x, building_aabb.min.y - size.y / 2.0));
+    }
+
+    if basement {
+        positions.push(Vec2::new(builder_pos.x, GROUND_LEVEL - size.y / 2.0));
+    }
+
+    if positions.is_empty() {
         preview_building.visible = false;
+        pb_transform.translation = builder_pos.extend(0.0);
+        return;
     }
-    // NOTE: no case for putting beneath
+    positions.push(Vec2::new(builder_pos.x, GROUND_LEVEL + size.y / 2.0));
+
+    let slots = positions.into_iter().map(|position| {
+        check_slot(
+            &spatial_query,
+            pb_collider,
+            position,
+            size,
+            preview_building.variant,
+        )
+    });
+
+    // valid slots first, then the closest one to the cursor
+    let best = slots
+        .min_by(|a, b| {
+            let a_valid = !a.blocked && a.bottom_support;
+            let b_valid = !b.blocked && b.bottom_support;
+            b_valid.cmp(&a_valid).then(
+                a.position
+                    .distance(builder_pos)
+                    .total_cmp(&b.position.distance(builder_pos)),
+            )
+        })
+        .unwrap();
+
+    preview_building.visible = true;
+    preview_building.blocked = best.blocked;
+    preview_building.bottom_support = best.bottom_support;
+    pb_transform.translation = best.position.extend(0.0);
+}
+
+/// Checks if a building with the given collider would be blocked at the position
+/// and if there is enough support underneath
+fn check_slot(
+    spatial_query: &SpatialQuery,
+    collider: &Collider,
+    position: Vec2,
+    size: Vec2,
+    variant: BuildingVariants,
+) -> PreviewSlot {
+    // a penthouse spans two supports
+    let required_supports = match variant {
+        BuildingVariants::Penthouse => 2,
+        _ => 1,
+    };
 
-    // Checks if found spot is free
-    if pb_colliding_entities.is_empty() {
-        // Free to place building
-        preview_building.blocked = false;
+    // basements are dug into the plates, they only have to keep away from buildings
+    let basement = matches!(variant, BuildingVariants::Basement(_));
+    let mask = if basement {
+        LayerMask::from([Layers::Building, Layers::Chimney])
     } else {
-        preview_building.blocked = true;
-    }
+        LayerMask::from([Layers::Building, Layers::Chimney, Layers::Plates])
+    };
+
+    // and they have to be dug into at least one plate to be held
+    let in_plates = !basement
+        || !spatial_query
+            .shape_intersections(
+                collider,
+                position,
+                0.0,
+                SpatialQueryFilter::from_mask(Layers::Plates),
+            )
+            .is_empty();
+
+    let blocked = !spatial_query
+        .shape_intersections(collider, position, 0.0, SpatialQueryFilter::from_mask(mask))
+        .is_empty()
+        || (basement && position.y + size.y / 2.0 > GROUND_LEVEL + 1.0)
+        || !in_plates;
 
-    // Checks if found spot has a building beneath
-    let bottom_support_sensor = bottom_support_sensors.single();
-    preview_building.bottom_support =
-        !bottom_support_sensor.is_empty() || pb_transform.translation.y < 1.0;
+    let supports = spatial_query
+        .shape_intersections(
+            &Collider::rectangle(0.9 * size.x, SUPPORT_CHECK_HEIGHT),
+            position - Vec2::Y * (size.y + SUPPORT_CHECK_HEIGHT) / 2.0,
+            0.0,
+            SpatialQueryFilter::from_mask(Layers::Building),
+        )
+        .len();
+    let on_ground = position.y - size.y / 2.0 <= GROUND_LEVEL + 1.0;
+
+    // wide buildings may only hang over a bit
+    let supported_samples = (0..SUPPORT_SAMPLES)
+        .filter(|i| {
+            let x = (*i as f32 / (SUPPORT_SAMPLES - 1) as f32 - 0.5) * 0.9 * size.x;
+            spatial_query
+                .cast_ray(
+                    position + Vec2::new(x, -size.y / 2.0 + 1.0),
+                    Dir2::NEG_Y,
+                    SUPPORT_CHECK_HEIGHT,
+                    true,
+                    SpatialQueryFilter::from_mask(Layers::Building),
+                )
+                .is_some()
+        })
+        .count();
+    let supported_fraction = supported_samples as f32 / SUPPORT_SAMPLES as f32;
+
+    PreviewSlot {
+        position,
+        blocked,
+        bottom_support: on_ground
+            || (supports >= required_supports && supported_fraction >= MIN_SUPPORTED_FRACTION),
+    }
 }
 
 /// Checks if left mouse button was pressed and preview building is visible
 fn maybe_send_place_building_event(
     preview_buildings: Query<&PreviewBuilding>,
     mouse: Res<ButtonInput<MouseButton>>,
+    keys: Res<ButtonInput<KeyCode>>,
     mut events: EventWriter<PlaceBuildingEvent>,
 ) {
     if preview_buildings.is_empty() {
@@ -445,34 +1960,85 @@ fn maybe_send_place_building_event(
 
     let preview_building = preview_buildings.single();
 
-    if mouse.just_released(MouseButton::Left)
-        && preview_building.visible
-        && !preview_building.blocked
-        && preview_building.bottom_support
+    if !mouse.just_released(MouseButton::Left)
+        || !preview_building.visible
+        || preview_building.blocked
     {
-        dbg!(events.send(PlaceBuildingEvent));
+        return;
+    }
+
+    // blueprints may wait for earlier blueprints to support them
+    if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        events.send(PlaceBuildingEvent { blueprint: true });
+    } else if preview_building.bottom_support {
+        events.send(PlaceBuildingEvent { blueprint: false });
     }
 }
 
-/// realise the preview building into a real building
+/// realise the preview building into a building under construction, or plan it as blueprint
 fn handle_place_building_event(
     mut cmd: Commands,
     mut events: EventReader<PlaceBuildingEvent>,
-    mut preview_buildings: Query<(Entity, &mut PreviewBuilding, &GlobalTransform)>,
-    pb_bottom_support_sensors: Query<Entity, With<PreviewBuildingBottomSupportSensor>>,
-    mut inhabitants: EventWriter<SpawnNewInhabitant>,
+    mut preview_buildings: Query<(
+        Entity,
+        &mut PreviewBuilding,
+        &mut PieceQueue,
+        &GlobalTransform,
+    )>,
     mut player: ResMut<Player>,
+    mut blueprints: ResMut<Blueprints>,
+    mut done: EventWriter<BuildActionDone>,
     assets: Res<AssetServer>,
 ) {
-    if events.is_empty() || player.money < 500 {
-        return;
-    }
+    let blueprint = match events.read().last() {
+        Some(event) => event.blueprint,
+        None => return,
+    };
     events.clear();
 
-    player.money -= 500;
+    let (pb_entity, mut preview_building, mut queue, transform) = preview_buildings.single_mut();
+
+    let plan = BuildingPlan {
+        position: transform.translation().xy(),
+        size: preview_building.size,
+        variant: preview_building.variant,
+        material: preview_building.material,
+    };
+
+    if blueprint {
+        blueprints.queue.push_back(Blueprint::Building(plan));
+    } else {
+        if player.money < CONSTRUCTION_DOWN_PAYMENT {
+            return;
+        }
+        player.money -= CONSTRUCTION_DOWN_PAYMENT;
+
+        let entity = spawn_building_under_construction(&mut cmd, &assets, &plan);
+        done.send(BuildActionDone {
+            blueprint: Blueprint::Building(plan),
+            entity,
+            cost: CONSTRUCTION_DOWN_PAYMENT,
+        });
+    }
+
+    match queue.dug.take() {
+        Some(piece) => piece,
+        None => queue.next(),
+    }
+    .apply(&mut preview_building);
 
-    let (pb_entity, mut preview_building, transform) = preview_buildings.single_mut();
+    cmd.entity(pb_entity).insert(preview_building_collider(
+        preview_building.size,
+        preview_building.variant,
+    ));
+}
 
+/// Spawns the planned building as a fresh construction site, the down payment has to be paid already
+fn spawn_building_under_construction(
+    cmd: &mut Commands,
+    assets: &AssetServer,
+    plan: &BuildingPlan,
+) -> Entity {
     let apartments = [
         "apartmentBLUE.png",
         "apartmentBLUE2.png",
@@ -485,24 +2051,49 @@ fn handle_place_building_event(
 
     let mut rng = rand::thread_rng();
 
-    let building = cmd
-        .spawn(BuildingBundle {
-            building: Building {
-                size: preview_building.size,
-            },
-            collider: Collider::rectangle(preview_building.size.x, preview_building.size.y),
-            rigidbody: RigidBody::Dynamic,
-            layers: building_layers(),
-            sprite: SpriteBundle {
-                texture: assets.load(apartments.choose(&mut rng).unwrap().to_string()),
-                sprite: Sprite {
-                    custom_size: Some(preview_building.size),
+    let texture = match plan.variant {
+        BuildingVariants::Foundation | BuildingVariants::Basement(_) => "ground.png",
+        _ => apartments.choose(&mut rng).unwrap(),
+    };
+
+    // basements are drawn over the plates they are dug into
+    let (layers, z) = match plan.variant {
+        BuildingVariants::Basement(_) => (basement_layers(), 0.5),
+        _ => (building_layers(), 0.0),
+    };
+
+    let entity = cmd
+        .spawn((
+            BuildingBundle {
+                building: Building {
+                    size: plan.size,
+                    cost: plan.cost(),
+                    material: plan.material,
+                    variant: plan.variant,
+                    health: BUILDING_HEALTH,
+                },
+                stress: BuildingStress::default(),
+                collider: construction_collider(plan.size, CONSTRUCTION_MIN_FRACTION),
+                rigidbody: RigidBody::Dynamic,
+                layers,
+                density: ColliderDensity(plan.material.density() * CONSTRUCTION_DENSITY_FACTOR),
+                friction: Friction::new(CONSTRUCTION_FRICTION),
+                sprite: SpriteBundle {
+                    texture: assets.load(texture.to_string()),
+                    sprite: construction_sprite(
+                        plan.size,
+                        CONSTRUCTION_MIN_FRACTION,
+                        plan.material.tint(),
+                    ),
+                    transform: Transform::from_translation(plan.position.extend(z)),
                     ..default()
                 },
-                transform: transform.compute_transform(),
-                ..default()
             },
-        })
+            UnderConstruction {
+                progress: Timer::from_seconds(CONSTRUCTION_SECONDS, TimerMode::Once),
+                paid: CONSTRUCTION_DOWN_PAYMENT,
+            },
+        ))
         .id();
 
     cmd.spawn(AudioBundle {
@@ -514,413 +2105,2458 @@ fn handle_place_building_event(
         },
     });
 
-    match preview_building.variant {
-        BuildingVariants::Default => (),
-        BuildingVariants::Chimney(offset) => {
-            let chimney = cmd
-                .spawn((
-                    Chimney,
-                    Sensor,
-                    Collider::rectangle(20.0, 30.0), // HACK: remove hardcoded
-                    chimney_layers(),
-                    SpriteBundle {
-                        texture: assets.load("chimney.png"),
-                        transform: Transform::from_translation(offset.extend(0.0)),
-                        ..default()
-                    },
-                ))
-                .id();
-            cmd.entity(building).add_child(chimney);
-        }
-    }
+    entity
+}
 
-    inhabitants.send(SpawnNewInhabitant(building));
+/// Puts the current building into the hold slot and takes out the held one,
+/// or the next one if nothing is held yet
+fn hold_preview_building(
+    mut cmd: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut preview_buildings: Query<(Entity, &mut PreviewBuilding, &mut PieceQueue)>,
+) {
+    if !keys.just_released(KeyCode::KeyH) {
+        return;
+    }
 
-    // Create new preview building
-    let mut rng = rand::thread_rng();
-    preview_building.size.x = rng.gen_range(80..=100) as f32;
+    let (pb_entity, mut preview_building, mut queue) = preview_buildings.single_mut();
 
-    cmd.entity(pb_bottom_support_sensors.single())
-        .insert(Collider::rectangle(0.9 * preview_building.size.x, 10.0));
+    // basements are not part of the queue
+    if queue.swapped || queue.dug.is_some() {
+        return;
+    }
 
-    let variants = [
-        BuildingVariants::Default,
-        BuildingVariants::Default,
-        BuildingVariants::Default,
-        BuildingVariants::Default,
-        BuildingVariants::Chimney(Vec2::ZERO),
-    ];
+    let current = Piece::from_preview(&preview_building);
+    let next = match queue.hold.take() {
+        Some(x) => x,
+        None => queue.next(),
+    };
+    queue.hold = Some(current);
+    queue.swapped = true;
 
-    let new_variant = variants.choose(&mut rng).unwrap();
+    next.apply(&mut preview_building);
+    cmd.entity(pb_entity).insert(preview_building_collider(
+        preview_building.size,
+        preview_building.variant,
+    ));
+}
 
-    dbg!(new_variant);
+/// Switches the preview building to a basement, then the other basement room and back again
+fn dig_preview_building(
+    mut cmd: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut preview_buildings: Query<(Entity, &mut PreviewBuilding, &mut PieceQueue)>,
+) {
+    if !keys.just_released(KeyCode::KeyG) {
+        return;
+    }
 
-    let default_collider = Collider::rectangle(
-        preview_building.size.x - PREVIEW_BUILDING_EPS,
-        preview_building.size.y - PREVIEW_BUILDING_EPS,
-    );
+    let (pb_entity, mut preview_building, mut queue) = preview_buildings.single_mut();
 
-    match new_variant {
-        BuildingVariants::Default => {
-            preview_building.variant = BuildingVariants::Default;
-            cmd.entity(pb_entity).insert(default_collider);
+    match preview_building.variant {
+        BuildingVariants::Basement(BasementRoom::Storage) => {
+            preview_building.variant = BuildingVariants::Basement(BasementRoom::Shelter);
         }
-        BuildingVariants::Chimney(_) => {
-            let offset = 0.4 * preview_building.size.x;
-            let x_offset = rng.gen_range(-offset..=offset) as f32;
-            let chimney_collider = Collider::rectangle(20.0, 30.0);
-            let chimney_offset = Vec2::new(x_offset, 30.0 + 20.0);
-            preview_building.variant = BuildingVariants::Chimney(chimney_offset);
-            cmd.entity(pb_entity).insert(Collider::compound(vec![
-                (Vec2::ZERO, 0.0, default_collider),
-                (chimney_offset, 0.0, chimney_collider),
-            ]));
+        BuildingVariants::Basement(BasementRoom::Shelter) => match queue.dug.take() {
+            Some(piece) => piece.apply(&mut preview_building),
+            None => preview_building.variant = BuildingVariants::Default,
+        },
+        _ => {
+            queue.dug = Some(Piece::from_preview(&preview_building));
+            preview_building.variant = BuildingVariants::Basement(BasementRoom::Storage);
         }
     }
+
+    cmd.entity(pb_entity).insert(preview_building_collider(
+        preview_building.size,
+        preview_building.variant,
+    ));
 }
 
-/// draws outline of preview building
-fn display_preview_building(
-    preview_buildings: Query<(&PreviewBuilding, &GlobalTransform)>,
-    mut gizmos: Gizmos,
+/// Shows the upcoming buildings and the hold slot
+fn update_piece_queue_label(
+    mut labels: Query<&mut Text, With<PieceQueueLabel>>,
+    queues: Query<&PieceQueue>,
 ) {
-    let (pb, transform) = preview_buildings.single();
+    let queue = queues.single();
 
-    match pb.variant {
-        BuildingVariants::Chimney(offset) => {
-            gizmos.rect_2d(
-                transform.translation().xy() + offset,
-                0.0,
-                Vec2::new(20.0, 30.0),
-                if !pb.visible || pb.blocked {
-                    RED
-                } else {
-                    GREEN
-                },
-            );
-        }
-        BuildingVariants::Default => (),
+    let upcoming: Vec<String> = queue.upcoming.iter().map(Piece::label).collect();
+    let hold = match queue.hold {
+        Some(piece) => piece.label(),
+        None => "-".to_string(),
     };
 
-    gizmos.rect_2d(
-        transform.translation().xy(),
-        0.0,
-        pb.size,
-        if !pb.visible || pb.blocked {
-            RED
-        } else {
-            GREEN
-        },
-    );
-
-    gizmos.arrow_2d(
-        transform.translation().xy(),
-        transform.translation().xy() - Vec2::Y * 25.0,
-        if !pb.bottom_support { RED } else { GREEN },
-    );
+    for mut label in labels.iter_mut() {
+        label.sections[0].value = format!("Next: {} | Hold (H): {}", upcoming.join(", "), hold);
+    }
 }
 
-/// Finds building which is clsoest to the cursor
-fn find_building_closest_to_cursor(
-    builder_collisions: &CollidingEntities,
-    building_colliders: &Query<(&GlobalTransform, &Collider, &ColliderAabb), With<Building>>,
-    builder_transform: &GlobalTransform,
-) -> Option<Entity> {
-    let mut same_row = false;
-    let mut min_distance = f32::MAX;
-
-    let mut closest_building: Option<Entity> = None;
+/// Builds the front blueprint once nothing else is under construction and it can be afforded.
+/// Buildings are checked again, if their place got blocked or their support collapsed they are
+/// dropped, the same goes for joints whose ends are gone.
+fn build_blueprints(
+    mut cmd: Commands,
+    mut blueprints: ResMut<Blueprints>,
+    mut player: ResMut<Player>,
+    entities: Query<Has<UnderConstruction>, With<GlobalTransform>>,
+    spatial_query: SpatialQuery,
+    mut done: EventWriter<BuildActionDone>,
+    assets: Res<AssetServer>,
+) {
+    // wait for earlier blueprints, they might be the support of the next one
+    if entities.iter().any(|under_construction| under_construction) {
+        return;
+    }
 
-    for collision_entity in builder_collisions.iter() {
-        let (building_transform, building_collider, building_aabb) =
-            match building_colliders.get(*collision_entity) {
-                Ok(x) => x,
-                Err(_) => continue,
-            };
+    let blueprint = match blueprints.queue.front() {
+        Some(x) => *x,
+        None => return,
+    };
 
-        let building_xy = building_transform.translation().xy();
+    match blueprint {
+        Blueprint::Building(plan) => {
+            let slot = check_slot(
+                &spatial_query,
+                &preview_building_collider(plan.size, plan.variant),
+                plan.position,
+                plan.size,
+                plan.variant,
+            );
 
-        let distance_to_point = building_collider.distance_to_point(
-            building_xy,
-            0.0,
-            builder_transform.translation().xy(),
-            true,
-        );
+            if slot.blocked || !slot.bottom_support {
+                blueprints.queue.pop_front();
+                return;
+            }
 
-        let building_same_row = building_aabb.max.y >= builder_transform.translation().y
-            && building_aabb.min.y <= builder_transform.translation().y;
+            // only start when it can be finished, so it does not stall halfway
+            if player.money < plan.cost() {
+                return;
+            }
+            player.money -= CONSTRUCTION_DOWN_PAYMENT;
 
-        // NOTE: setup to favour buildings in the same "row" / "level"
-        if !building_same_row && same_row {
-            continue;
+            let entity = spawn_building_under_construction(&mut cmd, &assets, &plan);
+            done.send(BuildActionDone {
+                blueprint,
+                entity,
+                cost: CONSTRUCTION_DOWN_PAYMENT,
+            });
         }
+        Blueprint::Joint(plan) => {
+            if !entities.contains(plan.entity1) || !entities.contains(plan.entity2) {
+                blueprints.queue.pop_front();
+                return;
+            }
+
+            if player.money < plan.cost() {
+                return;
+            }
+            player.money -= plan.cost();
+
+            let entity = plan.spawn(&mut cmd);
+            done.send(BuildActionDone {
+                blueprint,
+                entity,
+                cost: plan.cost(),
+            });
 
-        if building_same_row && !same_row {
-            same_row = true;
-            min_distance = distance_to_point;
-            closest_building = Some(*collision_entity);
-        } else if distance_to_point < min_distance {
-            min_distance = distance_to_point;
-            closest_building = Some(*collision_entity);
+            cmd.spawn(AudioBundle {
+                source: assets.load("build.ogg"),
+                settings: PlaybackSettings {
+                    mode: bevy::audio::PlaybackMode::Despawn,
+                    volume: Volume::new(0.8),
+                    ..default()
+                },
+            });
         }
     }
-    closest_building
+
+    blueprints.queue.pop_front();
 }
 
-/// Update what build op is selected by keyboard shortcuts
-fn update_selected_build_op(
-    keys: Res<ButtonInput<KeyCode>>,
-    mut build_ops: ResMut<SelectedBuildOps>,
-    mut joint_preview: Query<&mut BuildingJointPreview>,
+/// Puts everything that was built into the undo history, a new action makes redo impossible
+fn record_build_actions(
+    mut events: EventReader<BuildActionDone>,
+    mut history: ResMut<BuildHistory>,
+    time: Res<Time>,
 ) {
-    if keys.just_released(KeyCode::KeyB) {
-        build_ops.selected = BuildOps::Building;
-
-        joint_preview.single_mut().entity_start = None;
-    } else if keys.just_released(KeyCode::KeyJ) {
-        build_ops.selected = BuildOps::Joint;
+    for event in events.read() {
+        history.undo.push(BuildRecord {
+            blueprint: event.blueprint,
+            entity: event.entity,
+            cost: event.cost,
+            time: time.elapsed_seconds(),
+            inhabitants: vec![],
+        });
+        history.redo.clear();
     }
 }
 
-/// Checks if a building was clicked -> then either set it as start point or send event to create
-/// new joint
-fn check_building_clicked_for_joint(
+/// Removes the last build action and refunds what was paid for it, everything within the grace
+/// window and like demolishing afterwards. Inhabitants of an undone building move to the closest
+/// remaining building and the joints attached to it are removed too.
+fn undo_build_action(
     mut cmd: Commands,
-    windows: Query<&Window>,
-    cameras: Query<(&Camera, &GlobalTransform)>,
-    mouse: Res<ButtonInput<MouseButton>>,
-    spatial_query: SpatialQuery,
-    mut previews: Query<&mut BuildingJointPreview>,
-    transforms: Query<&GlobalTransform>,
+    mut history: ResMut<BuildHistory>,
     mut player: ResMut<Player>,
-    assets: Res<AssetServer>,
+    (time, earthquake): (Res<Time>, Res<EarthquakeTimer>),
+    buildings: Query<(
+        Entity,
+        &Building,
+        &GlobalTransform,
+        Option<&UnderConstruction>,
+    )>,
+    joints: Query<(Entity, &BuildingJoint)>,
+    inhabitants: Query<(Entity, &Parent), With<Inhabitant>>,
 ) {
-    if !mouse.just_released(MouseButton::Left) {
-        return;
+    // things which were destroyed in the meantime can not be undone anymore
+    while let Some(record) = history.undo.last() {
+        if buildings.contains(record.entity) || joints.contains(record.entity) {
+            break;
+        }
+        history.undo.pop();
     }
 
-    let window = windows.single();
-    let (camera, camera_transform) = cameras.single();
-
-    let cursor_position = match window.cursor_position() {
-        Some(x) => x,
+    let mut record = match history.undo.last() {
+        Some(x) => x.clone(),
         None => return,
     };
 
-    let cursor_world_position = match camera.viewport_to_world_2d(camera_transform, cursor_position)
-    {
-        Some(x) => x,
-        None => return,
-    };
+    let in_grace = time.elapsed_seconds() - record.time <= UNDO_GRACE_SECONDS;
+    if earthquake.is_running() && !in_grace {
+        return;
+    }
+    history.undo.pop();
 
-    if let Some(projected) = spatial_query.project_point(
-        cursor_world_position,
-        true,
-        SpatialQueryFilter::from_mask(Layers::Building),
-    ) {
-        let mut preview = previews.single_mut();
+    let mut refund = record.cost;
+    if let Ok((_, building, transform, construction)) = buildings.get(record.entity) {
+        refund = match construction {
+            Some(construction) => construction.paid,
+            None => building.cost,
+        };
 
-        if !projected.is_inside {
-            // NOTE: should this clear the previous?
-            preview.entity_start = None;
-        } else {
-            let transform = match transforms.get(projected.entity) {
-                Ok(x) => x,
-                Err(_) => return, // NOTE: silend failing...
-            };
+        for (joint_entity, joint) in &joints {
+            if joint.entity1 == record.entity || joint.entity2 == record.entity {
+                refund += joint.cost;
+                cmd.entity(joint_entity).despawn_recursive();
+            }
+        }
 
-            // NOTE: assuming no rotation....
-            // TODO: rotate
-            let mut local_offset = projected.point - transform.translation().xy();
-            let rotation = Vec2::from_angle(-transform.right().xy().to_angle());
-            local_offset = rotation.rotate(local_offset);
+        let new_home = buildings
+            .iter()
+            .filter(|(entity, _, _, construction)| {
+                *entity != record.entity && construction.is_none()
+            })
+            .min_by(|(_, _, a, _), (_, _, b, _)| {
+                let distance_a = a.translation().distance(transform.translation());
+                let distance_b = b.translation().distance(transform.translation());
+                distance_a.total_cmp(&distance_b)
+            })
+            .map(|(entity, _, _, _)| entity);
 
-            if preview.entity_start.is_none() {
-                preview.entity_start = Some(projected.entity);
-                preview.local_start = local_offset;
-            } else {
-                let transform_start = match transforms.get(preview.entity_start.unwrap()) {
-                    Ok(x) => x,
-                    Err(_) => return, // NOTE: silend failing...
-                };
+        for (inhabitant, parent) in &inhabitants {
+            if parent.get() != record.entity {
+                continue;
+            }
 
-                let cost =
-                    (transform_start.translation().xy().distance(projected.point) * 3.0) as i64;
-                if cost > player.money {
-                    return;
-                } else {
-                    player.money -= cost;
+            match new_home {
+                Some(home) => {
+                    cmd.entity(home).add_child(inhabitant);
+                    record.inhabitants.push(inhabitant);
                 }
+                None => cmd.entity(inhabitant).despawn_recursive(),
+            }
+        }
+    }
 
-                // preview.entity_end = Some(projected.entity);
-                // preview.local_end = local_offset;
-                //
-                let start_point = transform_start.translation().xy()
-                    + transform_start.right().xy().rotate(preview.local_start);
-
-                // probably should have been done with an event...
-                cmd.spawn((
-                    BuildingJoint,
-                    DistanceJoint::new(preview.entity_start.unwrap(), projected.entity)
-                        .with_local_anchor_1(preview.local_start)
-                        .with_local_anchor_2(local_offset)
-                        .with_rest_length(start_point.distance(projected.point)),
-                ));
+    if !in_grace {
+        refund = (refund as f32 * DEMOLISH_REFUND) as i64;
+    }
 
-                cmd.spawn(AudioBundle {
-                    source: assets.load("build.ogg"),
-                    settings: PlaybackSettings {
-                        mode: bevy::audio::PlaybackMode::Despawn,
-                        volume: Volume::new(0.8),
-                        ..default()
-                    },
-                });
+    player.money += refund;
+    cmd.entity(record.entity).despawn_recursive();
 
-                preview.entity_start = None;
+    history.redo.push(BuildRecord {
+        cost: refund,
+        ..record
+    });
+}
+
+/// Builds the last undone action again, if it still fits and can be paid
+fn redo_build_action(
+    mut cmd: Commands,
+    mut history: ResMut<BuildHistory>,
+    mut player: ResMut<Player>,
+    time: Res<Time>,
+    entities: Query<(), With<GlobalTransform>>,
+    spatial_query: SpatialQuery,
+    assets: Res<AssetServer>,
+) {
+    let record = match history.redo.last() {
+        Some(x) => x.clone(),
+        None => return,
+    };
+
+    let (entity, cost) = match record.blueprint {
+        Blueprint::Building(plan) => {
+            let slot = check_slot(
+                &spatial_query,
+                &preview_building_collider(plan.size, plan.variant),
+                plan.position,
+                plan.size,
+                plan.variant,
+            );
+            if slot.blocked || !slot.bottom_support || player.money < CONSTRUCTION_DOWN_PAYMENT {
+                return;
+            }
+            player.money -= CONSTRUCTION_DOWN_PAYMENT;
+
+            let entity = spawn_building_under_construction(&mut cmd, &assets, &plan);
+            if !record.inhabitants.is_empty() {
+                cmd.entity(entity)
+                    .insert(ReturningInhabitants(record.inhabitants.clone()));
+            }
+
+            (entity, CONSTRUCTION_DOWN_PAYMENT)
+        }
+        Blueprint::Joint(plan) => {
+            if !entities.contains(plan.entity1)
+                || !entities.contains(plan.entity2)
+                || player.money < plan.cost()
+            {
+                return;
+            }
+            player.money -= plan.cost();
+
+            (plan.spawn(&mut cmd), plan.cost())
+        }
+    };
+    history.redo.pop();
+
+    // joints waiting for redo have to be attached to the new building
+    for redo in history.redo.iter_mut() {
+        if let Blueprint::Joint(joint) = &mut redo.blueprint {
+            if joint.entity1 == record.entity {
+                joint.entity1 = entity;
+            }
+            if joint.entity2 == record.entity {
+                joint.entity2 = entity;
             }
         }
     }
+
+    history.undo.push(BuildRecord {
+        blueprint: record.blueprint,
+        entity,
+        cost,
+        time: time.elapsed_seconds(),
+        inhabitants: vec![],
+    });
 }
 
-/// Debug outline of buildings
-fn outline_buildings_system(buildings: Query<(&Building, &GlobalTransform)>, mut gizmos: Gizmos) {
-    for (building, transform) in &buildings {
-        let dir = transform.right();
-        let angle = dir.y.atan2(dir.x);
+/// Removes the last planned blueprint
+fn remove_last_blueprint(keys: Res<ButtonInput<KeyCode>>, mut blueprints: ResMut<Blueprints>) {
+    if keys.just_pressed(KeyCode::Backspace) {
+        blueprints.queue.pop_back();
+    }
+}
+
+/// Draws the planned buildings and joints like the preview, but in blue
+fn display_blueprints(
+    blueprints: Res<Blueprints>,
+    transforms: Query<&GlobalTransform>,
+    mut gizmos: Gizmos,
+) {
+    for blueprint in blueprints.queue.iter() {
+        match blueprint {
+            Blueprint::Building(plan) => {
+                gizmos.rect_2d(plan.position, 0.0, plan.size, DEEP_SKY_BLUE);
+
+                for (offset, size) in plan.variant.parts(plan.size) {
+                    gizmos.rect_2d(plan.position + offset, 0.0, size, DEEP_SKY_BLUE);
+                }
+
+                if let BuildingVariants::Chimney(offset) = plan.variant {
+                    gizmos.rect_2d(
+                        plan.position + offset,
+                        0.0,
+                        Vec2::new(20.0, 30.0),
+                        DEEP_SKY_BLUE,
+                    );
+                }
+            }
+            Blueprint::Joint(plan) => {
+                let (transform1, transform2) =
+                    match (transforms.get(plan.entity1), transforms.get(plan.entity2)) {
+                        (Ok(x), Ok(y)) => (x, y),
+                        _ => continue,
+                    };
+
+                let start = transform1.translation().xy()
+                    + transform1.right().xy().rotate(plan.local_anchor1);
+                let end = transform2.translation().xy()
+                    + transform2.right().xy().rotate(plan.local_anchor2);
+
+                gizmos.line_2d(start, end, DEEP_SKY_BLUE);
+                gizmos.circle_2d(start, 3.0, DEEP_SKY_BLUE);
+                gizmos.circle_2d(end, 3.0, DEEP_SKY_BLUE);
+            }
+        }
+    }
+}
+
+/// Collider of the preview building, inset so that touching buildings do not block it
+fn preview_building_collider(size: Vec2, variant: BuildingVariants) -> Collider {
+    let mut colliders = vec![(
+        Vec2::ZERO,
+        0.0,
+        Collider::rectangle(size.x - PREVIEW_BUILDING_EPS, size.y - PREVIEW_BUILDING_EPS),
+    )];
+
+    for (offset, size) in variant.parts(size) {
+        colliders.push((
+            offset,
+            0.0,
+            Collider::rectangle(size.x - PREVIEW_BUILDING_EPS, size.y - PREVIEW_BUILDING_EPS),
+        ));
+    }
+
+    if let BuildingVariants::Chimney(chimney_offset) = variant {
+        colliders.push((chimney_offset, 0.0, Collider::rectangle(20.0, 30.0)));
+    }
+
+    Collider::compound(colliders)
+}
+
+/// Collider of a building which is built up to the given fraction of its height,
+/// its base stays at the same place as the base of the finished building
+fn construction_collider(size: Vec2, fraction: f32) -> Collider {
+    let height = size.y * fraction;
+    Collider::compound(vec![(
+        Vec2::new(0.0, -(size.y - height) / 2.0),
+        0.0,
+        Collider::rectangle(size.x, height),
+    )])
+}
+
+/// Sprite of a building which is built up to the given fraction of its height
+fn construction_sprite(size: Vec2, fraction: f32, color: Color) -> Sprite {
+    let height = size.y * fraction;
+    Sprite {
+        color,
+        custom_size: Some(Vec2::new(size.x, height)),
+        // anchor is relative to the sprite size, move the sprite down to the base
+        anchor: Anchor::Custom(Vec2::new(0.0, (size.y - height) / (2.0 * height))),
+        ..default()
+    }
+}
+
+/// Grows buildings under construction and charges the player for every step.
+/// If the player runs out of money the construction stalls.
+fn progress_construction(
+    mut cmd: Commands,
+    mut constructions: Query<(
+        Entity,
+        &Building,
+        &Transform,
+        &mut UnderConstruction,
+        &mut Sprite,
+    )>,
+    (mut inhabitants, residents, returning): (
+        EventWriter<SpawnNewInhabitant>,
+        Query<(), With<Inhabitant>>,
+        Query<&ReturningInhabitants>,
+    ),
+    mut player: ResMut<Player>,
+    time: Res<Time>,
+    assets: Res<AssetServer>,
+) {
+    for (entity, building, transform, mut construction, mut sprite) in constructions.iter_mut() {
+        let duration = construction.progress.duration().as_secs_f32();
+        let next_fraction =
+            ((construction.progress.elapsed_secs() + time.delta_seconds()) / duration).min(1.0);
+
+        let owed = CONSTRUCTION_DOWN_PAYMENT
+            + ((building.cost - CONSTRUCTION_DOWN_PAYMENT) as f32 * next_fraction) as i64;
+        let due = owed - construction.paid;
+
+        if due > player.money {
+            // stalled until there is enough money
+            continue;
+        }
+        player.money -= due;
+        construction.paid += due;
+
+        construction.progress.tick(time.delta());
+
+        if !construction.progress.finished() {
+            let fraction = construction
+                .progress
+                .fraction()
+                .max(CONSTRUCTION_MIN_FRACTION);
+            *sprite = construction_sprite(building.size, fraction, building.material.tint());
+            cmd.entity(entity)
+                .insert(construction_collider(building.size, fraction));
+            continue;
+        }
+
+        // the rest of the price, e.g. lost to rounding
+        player.money -= building.cost - construction.paid;
+
+        *sprite = construction_sprite(building.size, 1.0, building.material.tint());
+        cmd.entity(entity)
+            .insert((
+                building.variant.collider(building.size),
+                ColliderDensity(building.material.density() * building.variant.density_factor()),
+                Friction::new(building.material.friction()),
+            ))
+            .remove::<UnderConstruction>();
+
+        match building.variant {
+            BuildingVariants::Chimney(offset) => {
+                let chimney = cmd
+                    .spawn((
+                        Chimney,
+                        Sensor,
+                        Collider::rectangle(20.0, 30.0), // HACK: remove hardcoded
+                        chimney_layers(),
+                        SpriteBundle {
+                            texture: assets.load("chimney.png"),
+                            transform: Transform::from_translation(offset.extend(0.0)),
+                            ..default()
+                        },
+                    ))
+                    .id();
+                cmd.entity(entity).add_child(chimney);
+            }
+            BuildingVariants::Balcony(_) | BuildingVariants::GardenRoof => {
+                let color = if building.variant == BuildingVariants::GardenRoof {
+                    Color::from(FOREST_GREEN)
+                } else {
+                    building.material.tint().mix(&GRAY.into(), 0.5)
+                };
+
+                for (offset, size) in building.variant.parts(building.size) {
+                    let part = cmd
+                        .spawn(SpriteBundle {
+                            sprite: Sprite {
+                                color,
+                                custom_size: Some(size),
+                                ..default()
+                            },
+                            transform: Transform::from_translation(offset.extend(0.0)),
+                            ..default()
+                        })
+                        .id();
+                    cmd.entity(entity).add_child(part);
+                }
+            }
+            BuildingVariants::Damper => spawn_damper(&mut cmd, entity, building, transform),
+            BuildingVariants::Default
+            | BuildingVariants::Foundation
+            | BuildingVariants::Penthouse
+            | BuildingVariants::FireStation
+            | BuildingVariants::Basement(_) => (),
+        }
+
+        // inhabitants of an undone building move back in, new ones fill the rest
+        let returning: Vec<Entity> = returning
+            .get(entity)
+            .map(|returning| returning.0.clone())
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|inhabitant| residents.contains(*inhabitant))
+            .take(building.variant.inhabitants())
+            .collect();
+        for inhabitant in &returning {
+            cmd.entity(entity).add_child(*inhabitant);
+        }
+        cmd.entity(entity).remove::<ReturningInhabitants>();
+
+        for _ in returning.len()..building.variant.inhabitants() {
+            inhabitants.send(SpawnNewInhabitant(entity));
+        }
+    }
+}
+
+/// Cancels a building under construction on right click and refunds part of the paid money
+fn cancel_construction_on_right_click(
+    mut cmd: Commands,
+    builders: Query<&GlobalTransform, With<CursorBuilder>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    spatial_query: SpatialQuery,
+    constructions: Query<&UnderConstruction>,
+    joints: Query<(Entity, &BuildingJoint)>,
+    mut player: ResMut<Player>,
+) {
+    if !mouse.just_released(MouseButton::Right) || builders.is_empty() {
+        return;
+    }
+
+    let projected = match spatial_query.project_point(
+        builders.single().translation().xy(),
+        true,
+        SpatialQueryFilter::from_mask(Layers::Building),
+    ) {
+        Some(x) if x.is_inside => x,
+        _ => return,
+    };
+
+    let construction = match constructions.get(projected.entity) {
+        Ok(x) => x,
+        Err(_) => return, // finished buildings can not be cancelled
+    };
+
+    player.money += (construction.paid as f32 * CONSTRUCTION_REFUND) as i64;
+
+    for (joint_entity, joint) in joints.iter() {
+        if joint.entity1 == projected.entity || joint.entity2 == projected.entity {
+            cmd.entity(joint_entity).despawn_recursive();
+        }
+    }
+
+    cmd.entity(projected.entity).despawn_recursive();
+}
+
+/// draws outline of preview building
+fn display_preview_building(
+    preview_buildings: Query<(&PreviewBuilding, &GlobalTransform)>,
+    mut gizmos: Gizmos,
+) {
+    let (pb, transform) = preview_buildings.single();
+
+    match pb.variant {
+        BuildingVariants::Chimney(offset) => {
+            gizmos.rect_2d(
+                transform.translation().xy() + offset,
+                0.0,
+                Vec2::new(20.0, 30.0),
+                if !pb.visible || pb.blocked {
+                    RED
+                } else {
+                    GREEN
+                },
+            );
+        }
+        BuildingVariants::Default
+        | BuildingVariants::Balcony(_)
+        | BuildingVariants::GardenRoof
+        | BuildingVariants::Foundation
+        | BuildingVariants::Penthouse
+        | BuildingVariants::Damper
+        | BuildingVariants::FireStation
+        | BuildingVariants::Basement(_) => (),
+    };
 
+    for (offset, size) in pb.variant.parts(pb.size) {
         gizmos.rect_2d(
-            transform.translation().xy(),
-            angle,
-            building.size,
-            DARK_SLATE_BLUE,
+            transform.translation().xy() + offset,
+            0.0,
+            size,
+            if !pb.visible || pb.blocked {
+                RED
+            } else {
+                GREEN
+            },
         );
     }
+
+    gizmos.rect_2d(
+        transform.translation().xy(),
+        0.0,
+        pb.size,
+        if !pb.visible || pb.blocked {
+            RED
+        } else {
+            GREEN
+        },
+    );
+
+    gizmos.arrow_2d(
+        transform.translation().xy(),
+        transform.translation().xy() - Vec2::Y * 25.0,
+        if !pb.bottom_support { RED } else { GREEN },
+    );
 }
 
-/// Debug outline of buildings
-fn outline_chimneys_system(chimneys: Query<&GlobalTransform, With<Chimney>>, mut gizmos: Gizmos) {
-    for transform in &chimneys {
-        let dir = transform.right();
-        let angle = dir.y.atan2(dir.x);
+/// Update what build op is selected by keyboard shortcuts
+fn update_selected_build_op(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut build_ops: ResMut<SelectedBuildOps>,
+    mut joint_preview: Query<&mut BuildingJointPreview>,
+    mut preview_buildings: Query<&mut PreviewBuilding>,
+) {
+    if keys.just_released(KeyCode::KeyB) {
+        build_ops.selected = BuildOps::Building;
 
-        gizmos.rect_2d(
-            transform.translation().xy(),
-            angle,
-            Vec2::new(20.0, 30.0),
-            DARK_GRAY,
+        joint_preview.single_mut().entity_start = None;
+    } else if keys.just_released(KeyCode::KeyJ) {
+        build_ops.selected = BuildOps::Joint;
+    } else if keys.just_released(KeyCode::KeyD) {
+        build_ops.selected = BuildOps::Demolish;
+
+        joint_preview.single_mut().entity_start = None;
+    } else if keys.just_released(KeyCode::KeyF) {
+        build_ops.selected = BuildOps::Extinguish;
+
+        joint_preview.single_mut().entity_start = None;
+    } else if keys.just_released(KeyCode::KeyR) {
+        build_ops.selected = BuildOps::Repair;
+
+        joint_preview.single_mut().entity_start = None;
+    } else if keys.just_released(KeyCode::KeyI) {
+        build_ops.selected = BuildOps::Isolate;
+
+        joint_preview.single_mut().entity_start = None;
+    }
+
+    match build_ops.selected {
+        BuildOps::Building => {
+            let materials = [
+                (KeyCode::Digit1, BuildingMaterial::Wood),
+                (KeyCode::Digit2, BuildingMaterial::Brick),
+                (KeyCode::Digit3, BuildingMaterial::Steel),
+                (KeyCode::Digit4, BuildingMaterial::ReinforcedConcrete),
+            ];
+
+            for (key, material) in materials {
+                if keys.just_released(key) {
+                    preview_buildings.single_mut().material = material;
+                }
+            }
+        }
+        BuildOps::Joint => {
+            let kinds = [
+                (KeyCode::Digit1, JointKind::Rope),
+                (KeyCode::Digit2, JointKind::Spring),
+                (KeyCode::Digit3, JointKind::Beam),
+                (KeyCode::Digit4, JointKind::Hinge),
+            ];
+
+            for (key, kind) in kinds {
+                if keys.just_released(key) {
+                    joint_preview.single_mut().kind = kind;
+                }
+            }
+        }
+        BuildOps::Demolish | BuildOps::Extinguish | BuildOps::Repair | BuildOps::Isolate => (),
+    }
+}
+
+/// Resizes the preview building with the mouse wheel, the width by default, the height while
+/// ctrl is held. Touchpads scroll in pixels, those are collected until they make a whole step.
+fn resize_preview_building(
+    mut cmd: Commands,
+    mut wheel: EventReader<MouseWheel>,
+    mut scrolled: Local<f32>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut preview_buildings: Query<(Entity, &mut PreviewBuilding)>,
+) {
+    for event in wheel.read() {
+        *scrolled += match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / SCROLL_PIXELS_PER_STEP,
+        };
+    }
+
+    let steps = scrolled.trunc();
+    if steps == 0.0 {
+        return;
+    }
+    *scrolled -= steps;
+
+    let (pb_entity, mut preview_building) = preview_buildings.single_mut();
+    let old_size = preview_building.size;
+
+    let mut size = old_size;
+    if keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        size.y += steps * BUILDING_SIZE_STEP;
+    } else {
+        size.x += steps * BUILDING_SIZE_STEP;
+    }
+    size = size.clamp(preview_building.variant.min_size(), MAX_BUILDING_SIZE);
+
+    // the chimney stays on the roof
+    if let BuildingVariants::Chimney(offset) = preview_building.variant {
+        preview_building.variant = BuildingVariants::Chimney(Vec2::new(
+            offset.x * size.x / old_size.x,
+            size.y / 2.0 + 20.0,
+        ));
+    }
+
+    preview_building.size = size;
+    cmd.entity(pb_entity).insert(preview_building_collider(
+        preview_building.size,
+        preview_building.variant,
+    ));
+}
+
+/// Shows the selected material in the tool label
+fn update_build_tool_label(
+    mut labels: Query<&mut Text, With<BuildToolLabel>>,
+    preview_buildings: Query<&PreviewBuilding>,
+) {
+    let preview = preview_buildings.single();
+
+    for mut label in labels.iter_mut() {
+        label.sections[0].value = format!(
+            "Build Tool (B): {} {}x{} (1-4, wheel, G dig)",
+            preview.material.name(),
+            preview.size.x,
+            preview.size.y
         );
     }
 }
 
-/// display preview joints
-/// NOTE: this currently also sets the length of the joint...
-fn display_preview_joint(
-    mut previews: Query<&mut BuildingJointPreview>,
-    transforms: Query<&GlobalTransform>,
+/// Shows the selected joint kind in the tool label
+fn update_joint_tool_label(
+    mut labels: Query<&mut Text, With<JointToolLabel>>,
+    joint_preview: Query<&BuildingJointPreview>,
+) {
+    let preview = joint_preview.single();
+
+    for mut label in labels.iter_mut() {
+        label.sections[0].value = format!("Support Joint Tool (J): {} (1-4)", preview.kind.name());
+    }
+}
+
+/// Checks if a building was clicked -> then either set it as start point or send event to create
+/// new joint
+fn check_building_clicked_for_joint(
+    mut cmd: Commands,
     windows: Query<&Window>,
     cameras: Query<(&Camera, &GlobalTransform)>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    spatial_query: SpatialQuery,
+    mut previews: Query<&mut BuildingJointPreview>,
+    transforms: Query<(&GlobalTransform, Has<Building>, Has<Plate>)>,
+    mut player: ResMut<Player>,
+    assets: Res<AssetServer>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut blueprints: ResMut<Blueprints>,
+    mut done: EventWriter<BuildActionDone>,
+) {
+    if !mouse.just_released(MouseButton::Left) {
+        return;
+    }
+
+    let window = windows.single();
+    let (camera, camera_transform) = cameras.single();
+
+    let cursor_position = match window.cursor_position() {
+        Some(x) => x,
+        None => return,
+    };
+
+    let cursor_world_position = match camera.viewport_to_world_2d(camera_transform, cursor_position)
+    {
+        Some(x) => x,
+        None => return,
+    };
+
+    if let Some(projected) = spatial_query.project_point(
+        cursor_world_position,
+        true,
+        SpatialQueryFilter::from_mask([Layers::Building, Layers::Plates, Layers::Ground]),
+    ) {
+        let mut preview = previews.single_mut();
+
+        if !projected.is_inside {
+            // NOTE: should this clear the previous?
+            preview.entity_start = None;
+        } else {
+            let (transform, is_building, is_plate) = match transforms.get(projected.entity) {
+                Ok(x) => x,
+                Err(_) => return, // NOTE: silend failing...
+            };
+            let anchor_end = JointAnchor::from_entity(is_building, is_plate);
+
+            // NOTE: assuming no rotation....
+            // TODO: rotate
+            let mut local_offset = projected.point - transform.translation().xy();
+            let rotation = Vec2::from_angle(-transform.right().xy().to_angle());
+            local_offset = rotation.rotate(local_offset);
+
+            if preview.entity_start.is_none() {
+                preview.entity_start = Some(projected.entity);
+                preview.local_start = local_offset;
+            } else {
+                let (transform_start, start_is_building, start_is_plate) =
+                    match transforms.get(preview.entity_start.unwrap()) {
+                        Ok(x) => x,
+                        Err(_) => return, // NOTE: silend failing...
+                    };
+                let anchor_start = JointAnchor::from_entity(start_is_building, start_is_plate);
+
+                if anchor_start != JointAnchor::Building && anchor_end != JointAnchor::Building {
+                    // at least one end has to be a building
+                    preview.entity_start = None;
+                    return;
+                }
+
+                let start_point = transform_start.translation().xy()
+                    + transform_start.right().xy().rotate(preview.local_start);
+                let length = start_point.distance(projected.point);
+
+                // beams and hinges connect both ends in the middle of the joint
+                let middle = start_point.lerp(projected.point, 0.5);
+                let middle_start = Vec2::from_angle(-transform_start.right().xy().to_angle())
+                    .rotate(middle - transform_start.translation().xy());
+                let middle_end = rotation.rotate(middle - transform.translation().xy());
+
+                // the building always comes first, so the second end is the anchor
+                let (entity1, local_anchor1, middle1, entity2, local_anchor2, middle2, anchor) =
+                    if anchor_start == JointAnchor::Building {
+                        (
+                            preview.entity_start.unwrap(),
+                            preview.local_start,
+                            middle_start,
+                            projected.entity,
+                            local_offset,
+                            middle_end,
+                            anchor_end,
+                        )
+                    } else {
+                        (
+                            projected.entity,
+                            local_offset,
+                            middle_end,
+                            preview.entity_start.unwrap(),
+                            preview.local_start,
+                            middle_start,
+                            anchor_start,
+                        )
+                    };
+
+                let plan = JointPlan {
+                    kind: preview.kind,
+                    entity1,
+                    local_anchor1,
+                    middle1,
+                    entity2,
+                    local_anchor2,
+                    middle2,
+                    length,
+                    anchor,
+                };
+
+                if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+                    blueprints.queue.push_back(Blueprint::Joint(plan));
+                    preview.entity_start = None;
+                    return;
+                }
+
+                let cost = plan.cost();
+                if cost > player.money {
+                    return;
+                } else {
+                    player.money -= cost;
+                }
+
+                // probably should have been done with an event...
+                let entity = plan.spawn(&mut cmd);
+                done.send(BuildActionDone {
+                    blueprint: Blueprint::Joint(plan),
+                    entity,
+                    cost,
+                });
+
+                cmd.spawn(AudioBundle {
+                    source: assets.load("build.ogg"),
+                    settings: PlaybackSettings {
+                        mode: bevy::audio::PlaybackMode::Despawn,
+                        volume: Volume::new(0.8),
+                        ..default()
+                    },
+                });
+
+                preview.entity_start = None;
+            }
+        }
+    }
+}
+
+/// Debug outline of buildings
+/// Colored from green to red by stress if the stress view is on
+fn outline_buildings_system(
+    buildings: Query<(&Building, &GlobalTransform, &BuildingStress)>,
+    view: Res<StressView>,
+    mut gizmos: Gizmos,
+) {
+    for (building, transform, stress) in &buildings {
+        let dir = transform.right();
+        let angle = dir.y.atan2(dir.x);
+
+        let color = if view.enabled {
+            Color::from(GREEN).mix(&Color::from(RED), stress.ratio.clamp(0.0, 1.0))
+        } else {
+            DARK_SLATE_BLUE.into()
+        };
+
+        gizmos.rect_2d(transform.translation().xy(), angle, building.size, color);
+
+        for (offset, size) in building.variant.parts(building.size) {
+            gizmos.rect_2d(
+                transform.translation().xy() + Vec2::from_angle(angle).rotate(offset),
+                angle,
+                size,
+                color,
+            );
+        }
+    }
+}
+
+/// Sums up the contact forces and the forces of attached joints for every building
+/// and how hard it was hit since the last frame
+fn update_building_stress(
+    collisions: Res<Collisions>,
+    (time, substeps): (Res<Time>, Res<Time<Substeps>>),
+    gravity: Res<Gravity>,
+    joints: Query<&BuildingJoint>,
+    mut buildings: Query<(&mut BuildingStress, &Mass, &LinearVelocity)>,
+) {
+    let fall = gravity.0 * time.delta_seconds();
+    for (mut stress, _, velocity) in buildings.iter_mut() {
+        stress.load = 0.0;
+        stress.impact = (velocity.0 - stress.velocity - fall).length();
+        stress.velocity = velocity.0;
+    }
+
+    let delta = substeps.delta_seconds();
+    if delta <= 0.0 {
+        return;
+    }
+
+    for contacts in collisions.iter() {
+        if contacts.is_sensor || !contacts.during_current_frame {
+            continue;
+        }
+
+        let force = contacts.total_normal_force(delta).abs();
+        for entity in [contacts.entity1, contacts.entity2] {
+            if let Ok((mut stress, _, _)) = buildings.get_mut(entity) {
+                stress.load += force;
+            }
+        }
+    }
+
+    for joint in &joints {
+        for entity in [joint.entity1, joint.entity2] {
+            if let Ok((mut stress, _, _)) = buildings.get_mut(entity) {
+                stress.load += joint.force.abs();
+            }
+        }
+    }
+
+    let gravity = gravity.0.length();
+    for (mut stress, mass, _) in buildings.iter_mut() {
+        let weight = mass.0 * gravity;
+        stress.ratio = if weight > 0.0 {
+            stress.load / (STRESS_CAPACITY * weight)
+        } else {
+            0.0
+        };
+    }
+}
+
+/// Buildings which are hit hard lose health, the harder the hit the more.
+/// Carrying a load doesn't hurt, so a tall tower can stand forever.
+fn damage_hit_buildings(
+    mut buildings: Query<(Entity, &mut Building, &BuildingStress)>,
+    mut damaged: EventWriter<BuildingDamaged>,
+) {
+    for (entity, mut building, stress) in buildings.iter_mut() {
+        let damage = (stress.impact - IMPACT_THRESHOLD).max(0.0) * IMPACT_DAMAGE;
+        if damage <= 0.0 {
+            continue;
+        }
+
+        building.health -= damage;
+        damaged.send(BuildingDamaged {
+            building: entity,
+            damage,
+        });
+    }
+}
+
+/// Breaks buildings without health into debris. The inhabitants are thrown into the closest
+/// building that is still standing, if there is none they die.
+fn collapse_destroyed_buildings(
+    mut cmd: Commands,
+    buildings: Query<(
+        Entity,
+        &Building,
+        &GlobalTransform,
+        &LinearVelocity,
+        &Handle<Image>,
+    )>,
+    joints: Query<(Entity, &BuildingJoint)>,
+    inhabitants: Query<(Entity, &Parent), With<Inhabitant>>,
+    images: Res<Assets<Image>>,
+    assets: Res<AssetServer>,
+    mut collapsed: EventWriter<BuildingCollapsed>,
+) {
+    for (entity, building, transform, velocity, texture) in &buildings {
+        if building.health > 0.0 {
+            continue;
+        }
+
+        let new_home = buildings
+            .iter()
+            .filter(|(other, other_building, _, _, _)| {
+                *other != entity && other_building.health > 0.0
+            })
+            .min_by(|(_, _, a, _, _), (_, _, b, _, _)| {
+                let distance_a = a.translation().distance(transform.translation());
+                let distance_b = b.translation().distance(transform.translation());
+                distance_a.total_cmp(&distance_b)
+            })
+            .map(|(other, _, _, _, _)| other);
+
+        for (inhabitant, parent) in &inhabitants {
+            if parent.get() != entity {
+                continue;
+            }
+
+            match new_home {
+                Some(home) => {
+                    cmd.entity(home).add_child(inhabitant);
+                }
+                None => cmd.entity(inhabitant).despawn_recursive(),
+            }
+        }
+
+        for (joint_entity, joint) in &joints {
+            if joint.entity1 == entity || joint.entity2 == entity {
+                cmd.entity(joint_entity).despawn_recursive();
+            }
+        }
+
+        // every piece shows its part of the texture
+        let texture_size = images.get(texture).map(|image| image.size_f32());
+        let piece_size = building.size / DEBRIS_PIECES.as_vec2();
+        let rotation = transform.compute_transform().rotation;
+        let mut rng = rand::thread_rng();
+
+        for x in 0..DEBRIS_PIECES.x {
+            for y in 0..DEBRIS_PIECES.y {
+                let piece = UVec2::new(x, y).as_vec2();
+                let offset = -building.size / 2.0 + piece_size * (piece + 0.5);
+                let position =
+                    transform.translation().xy() + rotation.mul_vec3(offset.extend(0.0)).xy();
+
+                // texture coordinates go downwards
+                let rect = texture_size.map(|size| {
+                    let min = Vec2::new(piece.x, DEBRIS_PIECES.y as f32 - 1.0 - piece.y) * size
+                        / DEBRIS_PIECES.as_vec2();
+                    Rect::from_corners(min, min + size / DEBRIS_PIECES.as_vec2())
+                });
+
+                cmd.spawn((
+                    Debris {
+                        lifetime: Timer::from_seconds(DEBRIS_SECONDS, TimerMode::Once),
+                    },
+                    RigidBody::Dynamic,
+                    Collider::rectangle(piece_size.x, piece_size.y),
+                    debris_layers(),
+                    ColliderDensity(building.material.density()),
+                    Friction::new(building.material.friction()),
+                    LinearVelocity(
+                        velocity.0
+                            + Vec2::new(
+                                rng.gen_range(-DEBRIS_SPREAD..=DEBRIS_SPREAD),
+                                rng.gen_range(0.0..=DEBRIS_SPREAD),
+                            ),
+                    ),
+                    AngularVelocity(rng.gen_range(-2.0..=2.0)),
+                    SpriteBundle {
+                        texture: texture.clone(),
+                        sprite: Sprite {
+                            color: building.material.tint().mix(&Color::from(DARK_GRAY), 0.3),
+                            custom_size: Some(piece_size),
+                            rect,
+                            ..default()
+                        },
+                        transform: Transform::from_translation(position.extend(0.0))
+                            .with_rotation(rotation),
+                        ..default()
+                    },
+                ));
+            }
+        }
+
+        cmd.spawn(AudioBundle {
+            source: assets.load("build.ogg"),
+            settings: PlaybackSettings {
+                mode: bevy::audio::PlaybackMode::Despawn,
+                volume: Volume::new(1.0),
+                speed: 0.5,
+                ..default()
+            },
+        });
+
+        collapsed.send(BuildingCollapsed);
+        cmd.entity(entity).despawn_recursive();
+    }
+}
+
+/// Removes old debris
+fn update_debris(mut cmd: Commands, time: Res<Time>, mut debris: Query<(Entity, &mut Debris)>) {
+    for (entity, mut piece) in debris.iter_mut() {
+        piece.lifetime.tick(time.delta());
+
+        if piece.lifetime.finished() {
+            cmd.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Puts crack sprites onto damaged buildings, more the less health is left,
+/// and takes them off again when the building is repaired
+fn update_cracks(
+    mut cmd: Commands,
+    buildings: Query<(Entity, &Building, Option<&Children>), Changed<Building>>,
+    cracks: Query<&Crack>,
+) {
+    for (entity, building, children) in &buildings {
+        let damage = 1.0 - building.health / BUILDING_HEALTH;
+        let wanted = if damage < 1.0 - CRACK_HEALTH {
+            0
+        } else {
+            ((damage * MAX_CRACKS as f32).ceil() as usize).min(MAX_CRACKS)
+        };
+
+        let mut shown = [false; MAX_CRACKS];
+        for child in children.into_iter().flatten() {
+            let Ok(crack) = cracks.get(*child) else {
+                continue;
+            };
+
+            if crack.0 < wanted {
+                shown[crack.0] = true;
+            } else {
+                cmd.entity(*child).despawn_recursive();
+            }
+        }
+
+        let half = building.size / 2.0;
+        for index in (0..wanted).filter(|index| !shown[*index]) {
+            // the same building always cracks the same way
+            let mut rng = StdRng::seed_from_u64(entity.to_bits().wrapping_add(index as u64));
+
+            let crack = cmd
+                .spawn((
+                    Crack(index),
+                    // just above the building
+                    SpatialBundle::from_transform(Transform::from_xyz(0.0, 0.0, 0.1)),
+                ))
+                .id();
+
+            let mut point = Vec2::new(rng.gen_range(-half.x..=half.x), half.y);
+            for _ in 0..4 {
+                let next = (point
+                    + Vec2::new(
+                        rng.gen_range(-0.15..=0.15) * building.size.x,
+                        -rng.gen_range(0.1..=0.25) * building.size.y,
+                    ))
+                .clamp(-half, half);
+                let segment = next - point;
+
+                let piece = cmd
+                    .spawn(SpriteBundle {
+                        sprite: Sprite {
+                            color: BLACK.into(),
+                            custom_size: Some(Vec2::new(segment.length(), CRACK_WIDTH)),
+                            ..default()
+                        },
+                        transform: Transform::from_translation(((point + next) / 2.0).extend(0.0))
+                            .with_rotation(Quat::from_rotation_z(segment.to_angle())),
+                        ..default()
+                    })
+                    .id();
+                cmd.entity(crack).add_child(piece);
+                point = next;
+            }
+
+            cmd.entity(entity).add_child(crack);
+        }
+    }
+}
+
+/// Toggles the stress view
+fn toggle_stress_view(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut view: ResMut<StressView>,
+    mut labels: Query<&mut Text, With<StressViewLabel>>,
+) {
+    if keys.just_released(KeyCode::KeyV) {
+        view.enabled = !view.enabled;
+    }
+
+    for mut label in labels.iter_mut() {
+        label.sections[0].value = format!(
+            "Stress View (V): {}",
+            if view.enabled { "on" } else { "off" }
+        );
+    }
+}
+
+/// Shows the load of the building under the cursor while the stress view is on
+fn update_stress_label(
+    view: Res<StressView>,
+    builders: Query<&GlobalTransform, With<CursorBuilder>>,
+    spatial_query: SpatialQuery,
+    buildings: Query<(&Building, &GlobalTransform, &BuildingStress)>,
+    mut labels: Query<(&mut Transform, &mut Text, &mut Visibility, &StressLabel)>,
+) {
+    let hovered = match (view.enabled, builders.get_single()) {
+        (true, Ok(builder)) => spatial_query
+            .project_point(
+                builder.translation().xy(),
+                true,
+                SpatialQueryFilter::from_mask(Layers::Building),
+            )
+            .filter(|projected| projected.is_inside)
+            .and_then(|projected| buildings.get(projected.entity).ok()),
+        _ => None,
+    };
+
+    for (mut transform, mut text, mut visibility, label) in labels.iter_mut() {
+        let (building, building_transform, stress) = match hovered {
+            Some(x) => x,
+            None => {
+                *visibility = Visibility::Hidden;
+                continue;
+            }
+        };
+
+        *visibility = Visibility::Visible;
+        transform.translation = (building_transform.translation().xy()
+            + Vec2::Y * (building.size.y / 2.0 + 30.0))
+            .extend(10.0)
+            + label.0;
+        text.sections[0].value = format!("{:.0} ({:.0}%)", stress.load, stress.ratio * 100.0);
+    }
+}
+
+/// Debug outline of buildings
+fn outline_chimneys_system(chimneys: Query<&GlobalTransform, With<Chimney>>, mut gizmos: Gizmos) {
+    for transform in &chimneys {
+        let dir = transform.right();
+        let angle = dir.y.atan2(dir.x);
+
+        gizmos.rect_2d(
+            transform.translation().xy(),
+            angle,
+            Vec2::new(20.0, 30.0),
+            DARK_GRAY,
+        );
+    }
+}
+
+/// display preview joints
+/// NOTE: this currently also sets the length of the joint...
+fn display_preview_joint(
+    mut previews: Query<&mut BuildingJointPreview>,
+    transforms: Query<(&GlobalTransform, Has<Building>, Has<Plate>)>,
+    windows: Query<&Window>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    spatial_query: SpatialQuery,
+    mut gizmos: Gizmos,
+) {
+    let mut preview = previews.single_mut();
+
+    let window = windows.single();
+    let (camera, camera_transform) = cameras.single();
+
+    let cursor_position = match window.cursor_position() {
+        Some(x) => x,
+        None => return,
+    };
+
+    let cursor_world_position = match camera.viewport_to_world_2d(camera_transform, cursor_position)
+    {
+        Some(x) => x,
+        None => return,
+    };
+
+    let mut anchor_start = JointAnchor::Building;
+
+    if let Some(e) = preview.entity_start {
+        let (transform, is_building, is_plate) = match transforms.get(e) {
+            Ok(x) => x,
+            Err(_) => return,
+        };
+        anchor_start = JointAnchor::from_entity(is_building, is_plate);
+
+        let point =
+            transform.translation().xy() + transform.right().xy().rotate(preview.local_start);
+
+        gizmos.circle_2d(point, 8.0, PURPLE);
+
+        gizmos.arrow_2d(point, cursor_world_position, WHITE);
+
+        preview.length = cursor_world_position.distance(point);
+    } else {
+        preview.length = 0.0;
+    }
+
+    let anchor_end = spatial_query
+        .project_point(
+            cursor_world_position,
+            true,
+            SpatialQueryFilter::from_mask([Layers::Building, Layers::Plates, Layers::Ground]),
+        )
+        .filter(|projected| projected.is_inside)
+        .and_then(|projected| transforms.get(projected.entity).ok())
+        .map(|(_, is_building, is_plate)| JointAnchor::from_entity(is_building, is_plate));
+
+    // anchors can only be connected to buildings
+    let targeting_valid = match anchor_end {
+        Some(JointAnchor::Building) => true,
+        Some(_) => anchor_start == JointAnchor::Building,
+        None => false,
+    };
+
+    preview.anchor = if anchor_start != JointAnchor::Building {
+        anchor_start
+    } else {
+        anchor_end.unwrap_or(JointAnchor::Building)
+    };
+
+    gizmos.circle_2d(
+        cursor_world_position,
+        8.0,
+        if targeting_valid {
+            GREEN
+        } else if preview.entity_start.is_some() {
+            ORANGE
+        } else {
+            RED
+        },
+    );
+}
+
+/// display joints, every kind is drawn differently
+fn display_joints(
+    joints: Query<&BuildingJoint>,
+    transforms: Query<&GlobalTransform>,
+    mut gizmos: Gizmos,
+) {
+    for joint in &joints {
+        let t1 = match transforms.get(joint.entity1) {
+            Ok(t) => t,
+            Err(_) => continue,
+        };
+
+        let t2 = match transforms.get(joint.entity2) {
+            Ok(t) => t,
+            Err(_) => continue,
+        };
+
+        let point1 = t1.translation().xy() + t1.right().xy().rotate(joint.local_anchor1);
+        let point2 = t2.translation().xy() + t2.right().xy().rotate(joint.local_anchor2);
+
+        gizmos.circle_2d(point1, 8.0, WHITE);
+        gizmos.circle_2d(point1, 7.0, PURPLE);
+        gizmos.circle_2d(point2, 8.0, WHITE);
+        gizmos.circle_2d(point2, 7.0, PURPLE);
+
+        // joints turn red the closer they are to snapping
+        let strain = (joint.force / joint.strength).clamp(0.0, 1.0);
+        let color = Color::from(WHITE).mix(&RED.into(), strain);
+        let shadow = Vec2::new(1.0, -1.0);
+
+        match joint.kind {
+            JointKind::Rope => {
+                // a slack rope sags down, the more slack the deeper
+                let sag = (point1.distance(point2) * (ROPE_SLACK - 1.0)).max(0.0);
+                let control = point1.lerp(point2, 0.5) - Vec2::Y * sag;
+                let points: Vec<Vec2> = (0..=16)
+                    .map(|i| {
+                        let t = i as f32 / 16.0;
+                        point1.lerp(control, t).lerp(control.lerp(point2, t), t)
+                    })
+                    .collect();
+
+                gizmos.linestrip_2d(points.iter().copied(), color);
+                gizmos.linestrip_2d(points.iter().map(|p| *p + shadow), BURLYWOOD);
+            }
+            JointKind::Spring => {
+                // zig zag coil between both ends
+                let dir = point2 - point1;
+                let normal = dir.perp().normalize_or_zero() * 6.0;
+                let coils = (dir.length() / 8.0).max(2.0) as i32;
+                let points: Vec<Vec2> = (0..=coils)
+                    .map(|i| {
+                        let offset = match i {
+                            0 => Vec2::ZERO,
+                            i if i == coils => Vec2::ZERO,
+                            i if i % 2 == 0 => normal,
+                            _ => -normal,
+                        };
+                        point1 + dir * (i as f32 / coils as f32) + offset
+                    })
+                    .collect();
+
+                gizmos.linestrip_2d(points.iter().copied(), color);
+                gizmos.linestrip_2d(points.iter().map(|p| *p + shadow), PURPLE);
+            }
+            JointKind::Beam => {
+                // thick beam made out of parallel lines
+                let normal = (point2 - point1).perp().normalize_or_zero();
+                for i in -2..=2 {
+                    let offset = normal * i as f32 * 1.5;
+                    gizmos.line_2d(point1 + offset, point2 + offset, color);
+                }
+                gizmos.line_2d(
+                    point1 + normal * 4.5 + shadow,
+                    point2 + normal * 4.5 + shadow,
+                    DARK_GRAY,
+                );
+            }
+            JointKind::Hinge => {
+                let middle = point1.lerp(point2, 0.5);
+
+                gizmos.line_2d(point1, point2, color);
+                gizmos.line_2d(point1 + shadow, point2 + shadow, PURPLE);
+                gizmos.circle_2d(middle, 6.0, WHITE);
+                gizmos.circle_2d(middle, 4.0, color);
+            }
+        }
+
+        if joint.anchor != JointAnchor::Building {
+            // stake driven into the ground
+            gizmos.line_2d(
+                point2 + Vec2::new(-10.0, 0.0),
+                point2 + Vec2::new(10.0, 0.0),
+                BROWN,
+            );
+            gizmos.line_2d(
+                point2 + Vec2::new(-10.0, 0.0),
+                point2 - Vec2::Y * 14.0,
+                BROWN,
+            );
+            gizmos.line_2d(
+                point2 + Vec2::new(10.0, 0.0),
+                point2 - Vec2::Y * 14.0,
+                BROWN,
+            );
+        }
+    }
+}
+
+/// Copies the force of the avian joint into the building joint
+fn update_joint_force<T: Component + JointForce>(mut joints: Query<(&mut BuildingJoint, &T)>) {
+    for (mut building_joint, joint) in joints.iter_mut() {
+        building_joint.force = joint.force_magnitude();
+    }
+}
+
+/// Snaps joints whose constraint force exceeds their strength
+fn break_overloaded_joints(
+    mut cmd: Commands,
+    joints: Query<(Entity, &BuildingJoint)>,
+    mut events: EventWriter<JointBroken>,
+) {
+    for (entity, joint) in &joints {
+        if joint.force <= joint.strength {
+            continue;
+        }
+
+        cmd.entity(entity).despawn_recursive();
+
+        events.send(JointBroken {
+            entity1: joint.entity1,
+            local_anchor1: joint.local_anchor1,
+            entity2: joint.entity2,
+            local_anchor2: joint.local_anchor2,
+            kind: joint.kind,
+            anchor: joint.anchor,
+        });
+    }
+}
+
+/// leaves the frayed ends of a broken joint behind and plays a snapping sound
+fn handle_joint_broken(
+    mut cmd: Commands,
+    mut events: EventReader<JointBroken>,
+    assets: Res<AssetServer>,
+) {
+    for broken in events.read() {
+        cmd.spawn(FrayedJoint {
+            entity1: broken.entity1,
+            local_anchor1: broken.local_anchor1,
+            entity2: broken.entity2,
+            local_anchor2: broken.local_anchor2,
+            kind: broken.kind,
+            anchor: broken.anchor,
+        });
+
+        cmd.spawn(AudioBundle {
+            source: assets.load("build.ogg"),
+            settings: PlaybackSettings {
+                mode: bevy::audio::PlaybackMode::Despawn,
+                volume: Volume::new(1.0),
+                speed: 2.5,
+                ..default()
+            },
+        });
+    }
+}
+
+/// display the loose ends of snapped joints, removes them once a building is gone
+fn display_frayed_joints(
+    mut cmd: Commands,
+    frayed_joints: Query<(Entity, &FrayedJoint)>,
+    transforms: Query<&GlobalTransform>,
+    mut gizmos: Gizmos,
+) {
+    for (entity, frayed) in &frayed_joints {
+        let (t1, t2) = match (
+            transforms.get(frayed.entity1),
+            transforms.get(frayed.entity2),
+        ) {
+            (Ok(t1), Ok(t2)) => (t1, t2),
+            _ => {
+                cmd.entity(entity).despawn_recursive();
+                continue;
+            }
+        };
+
+        let point1 = t1.translation().xy() + t1.right().xy().rotate(frayed.local_anchor1);
+        let point2 = t2.translation().xy() + t2.right().xy().rotate(frayed.local_anchor2);
+
+        for (from, to) in [(point1, point2), (point2, point1)] {
+            let dir = (to - from).normalize_or_zero();
+
+            gizmos.circle_2d(from, 8.0, WHITE);
+            gizmos.circle_2d(from, 7.0, PURPLE);
+
+            // a few loose strands pointing to where the other end used to be
+            for (angle, length) in [(-0.4, 14.0), (0.0, 22.0), (0.35, 17.0)] {
+                let strand = Vec2::from_angle(angle).rotate(dir) * length;
+                gizmos.line_2d(from, from + strand, WHITE);
+                gizmos.line_2d(
+                    from + Vec2::new(1.0, -1.0),
+                    from + strand + Vec2::new(1.0, -1.0),
+                    PURPLE,
+                );
+            }
+        }
+    }
+}
+
+/// Finds the building or joint under the cursor which would be demolished and highlights it
+fn update_demolish_preview(
+    builders: Query<&GlobalTransform, With<CursorBuilder>>,
+    spatial_query: SpatialQuery,
+    buildings: Query<(&Building, &GlobalTransform, Option<&UnderConstruction>)>,
+    joints: Query<(Entity, &BuildingJoint)>,
+    transforms: Query<&GlobalTransform>,
+    mut previews: Query<&mut DemolishPreview>,
+    mut gizmos: Gizmos,
+) {
+    if builders.is_empty() {
+        return;
+    }
+
+    let cursor = builders.single().translation().xy();
+    let mut preview = previews.single_mut();
+    preview.target = None;
+    preview.refund = 0;
+
+    // joints are thin, so they are preferred over the building behind them
+    let mut closest_joint_distance = DEMOLISH_JOINT_DISTANCE;
+    for (entity, joint) in &joints {
+        let (t1, t2) = match (transforms.get(joint.entity1), transforms.get(joint.entity2)) {
+            (Ok(t1), Ok(t2)) => (t1, t2),
+            _ => continue,
+        };
+
+        let point1 = t1.translation().xy() + t1.right().xy().rotate(joint.local_anchor1);
+        let point2 = t2.translation().xy() + t2.right().xy().rotate(joint.local_anchor2);
+
+        let segment = point2 - point1;
+        let t = ((cursor - point1).dot(segment) / segment.length_squared().max(f32::EPSILON))
+            .clamp(0.0, 1.0);
+        let distance = cursor.distance(point1 + segment * t);
+
+        if distance < closest_joint_distance {
+            closest_joint_distance = distance;
+            preview.target = Some(entity);
+            preview.refund = (joint.cost as f32 * DEMOLISH_REFUND) as i64;
+
+            gizmos.line_2d(point1, point2, RED);
+            gizmos.circle_2d(point1 + segment * t, 8.0, RED);
+        }
+    }
+
+    if preview.target.is_some() {
+        return;
+    }
+
+    let projected = match spatial_query.project_point(
+        cursor,
+        true,
+        SpatialQueryFilter::from_mask(Layers::Building),
+    ) {
+        Some(x) if x.is_inside => x,
+        _ => return,
+    };
+
+    let (building, transform, construction) = match buildings.get(projected.entity) {
+        Ok(x) => x,
+        Err(_) => return,
+    };
+
+    preview.target = Some(projected.entity);
+    preview.refund = match construction {
+        Some(construction) => (construction.paid as f32 * CONSTRUCTION_REFUND) as i64,
+        None => (building.cost as f32 * DEMOLISH_REFUND) as i64,
+    };
+
+    // the joints attached to the building are torn down as well
+    for (_, joint) in &joints {
+        if joint.entity1 == projected.entity || joint.entity2 == projected.entity {
+            preview.refund += (joint.cost as f32 * DEMOLISH_REFUND) as i64;
+        }
+    }
+
+    let angle = transform.right().xy().to_angle();
+    let center = transform.translation().xy();
+    let half = Vec2::from_angle(angle).rotate(building.size / 2.0);
+    let half_flipped =
+        Vec2::from_angle(angle).rotate(Vec2::new(building.size.x, -building.size.y) / 2.0);
+
+    gizmos.rect_2d(center, angle, building.size, RED);
+    gizmos.line_2d(center - half, center + half, RED);
+    gizmos.line_2d(center - half_flipped, center + half_flipped, RED);
+}
+
+/// Demolishes the previewed building or joint on click.
+/// Inhabitants move to the closest remaining building or are evicted if there is none.
+fn demolish_on_click(
+    mut cmd: Commands,
+    mouse: Res<ButtonInput<MouseButton>>,
+    previews: Query<&DemolishPreview>,
+    buildings: Query<(Entity, &GlobalTransform, Has<UnderConstruction>), With<Building>>,
+    joints: Query<(Entity, &BuildingJoint)>,
+    inhabitants: Query<(Entity, &Parent), With<Inhabitant>>,
+    mut player: ResMut<Player>,
+) {
+    if !mouse.just_released(MouseButton::Left) {
+        return;
+    }
+
+    let preview = previews.single();
+
+    let target = match preview.target {
+        Some(x) => x,
+        None => return,
+    };
+
+    player.money += preview.refund;
+
+    if let Ok((_, transform, _)) = buildings.get(target) {
+        let new_home = buildings
+            .iter()
+            .filter(|(entity, _, under_construction)| *entity != target && !under_construction)
+            .min_by(|(_, a, _), (_, b, _)| {
+                let distance_a = a.translation().distance(transform.translation());
+                let distance_b = b.translation().distance(transform.translation());
+                distance_a.total_cmp(&distance_b)
+            })
+            .map(|(entity, _, _)| entity);
+
+        for (inhabitant, parent) in &inhabitants {
+            if parent.get() != target {
+                continue;
+            }
+
+            match new_home {
+                Some(home) => {
+                    cmd.entity(home).add_child(inhabitant);
+                }
+                None => cmd.entity(inhabitant).despawn_recursive(),
+            }
+        }
+
+        for (joint_entity, joint) in &joints {
+            if joint.entity1 == target || joint.entity2 == target {
+                cmd.entity(joint_entity).despawn_recursive();
+            }
+        }
+    }
+
+    cmd.entity(target).despawn_recursive();
+}
+
+/// draws the damper masses and the springs they hang on
+fn display_dampers(
+    dampers: Query<(&DamperMass, &GlobalTransform)>,
+    transforms: Query<&GlobalTransform>,
+    mut gizmos: Gizmos,
+) {
+    for (damper, mass_transform) in &dampers {
+        let building_transform = match transforms.get(damper.building) {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+
+        let anchor = building_transform.translation().xy()
+            + building_transform.right().xy().rotate(damper.local_anchor);
+        let mass = mass_transform.translation().xy();
+
+        gizmos.line_2d(anchor, mass, DARK_GRAY);
+        gizmos.circle_2d(mass, DAMPER_RADIUS, DARK_GRAY);
+        gizmos.circle_2d(mass, DAMPER_RADIUS - 2.0, ORANGE);
+    }
+}
+
+/// Embeds new basements into the plates around them, they move with the plates and can't tip.
+/// They rest on the soil at the bottom of their pit in case the plates let go.
+fn embed_basements(
+    mut cmd: Commands,
+    basements: Query<(Entity, &Building, &Transform), Added<Building>>,
+    plates: Query<&GlobalTransform, With<Plate>>,
+    spatial_query: SpatialQuery,
+) {
+    for (entity, building, transform) in &basements {
+        if !matches!(building.variant, BuildingVariants::Basement(_)) {
+            continue;
+        }
+
+        let position = transform.translation.xy();
+        for plate in spatial_query.shape_intersections(
+            &Collider::rectangle(building.size.x, building.size.y),
+            position,
+            0.0,
+            SpatialQueryFilter::from_mask(Layers::Plates),
+        ) {
+            let plate_transform = match plates.get(plate) {
+                Ok(x) => x,
+                Err(_) => continue,
+            };
+
+            cmd.spawn((
+                BasementAnchor { basement: entity },
+                FixedJoint::new(plate, entity)
+                    .with_local_anchor_1(position - plate_transform.translation().xy()),
+            ));
+        }
+
+        let pit_floor = position - Vec2::Y * (building.size.y + PIT_FLOOR_HEIGHT) / 2.0;
+        cmd.spawn((
+            BasementAnchor { basement: entity },
+            RigidBody::Static,
+            Collider::rectangle(building.size.x, PIT_FLOOR_HEIGHT),
+            soil_layers(),
+            TransformBundle::from_transform(Transform::from_translation(pit_floor.extend(0.0))),
+        ));
+    }
+}
+
+/// Bolts finished buildings onto the basements they stand on, deeper basements hold more
+fn anchor_to_basements(
+    mut cmd: Commands,
+    mut finished: RemovedComponents<UnderConstruction>,
+    buildings: Query<(&Building, &GlobalTransform)>,
+    spatial_query: SpatialQuery,
+) {
+    for entity in finished.read() {
+        let (building, transform) = match buildings.get(entity) {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+
+        if matches!(building.variant, BuildingVariants::Basement(_)) {
+            continue;
+        }
+
+        let bottom = transform.translation().xy()
+            + transform
+                .right()
+                .xy()
+                .rotate(Vec2::new(0.0, -building.size.y / 2.0));
+
+        for basement in spatial_query.shape_intersections(
+            &Collider::rectangle(0.9 * building.size.x, SUPPORT_CHECK_HEIGHT),
+            bottom - Vec2::Y * SUPPORT_CHECK_HEIGHT / 2.0,
+            0.0,
+            SpatialQueryFilter::from_mask(Layers::Building),
+        ) {
+            let (basement_building, basement_transform) = match buildings.get(basement) {
+                Ok(x) => x,
+                Err(_) => continue,
+            };
+
+            if !matches!(basement_building.variant, BuildingVariants::Basement(_)) {
+                continue;
+            }
+
+            // bolted where both overlap
+            let basement_x = basement_transform.translation().x;
+            let left =
+                (bottom.x - building.size.x / 2.0).max(basement_x - basement_building.size.x / 2.0);
+            let right =
+                (bottom.x + building.size.x / 2.0).min(basement_x + basement_building.size.x / 2.0);
+            let point = Vec2::new((left + right) / 2.0, bottom.y);
+
+            let local_anchor1 = Vec2::from_angle(-transform.right().xy().to_angle())
+                .rotate(point - transform.translation().xy());
+            let local_anchor2 = Vec2::from_angle(-basement_transform.right().xy().to_angle())
+                .rotate(point - basement_transform.translation().xy());
+
+            JointPlan {
+                kind: JointKind::Beam,
+                entity1: entity,
+                local_anchor1,
+                middle1: local_anchor1,
+                entity2: basement,
+                local_anchor2,
+                middle2: local_anchor2,
+                length: 0.0,
+                anchor: JointAnchor::Basement(basement_building.size.y / REFERENCE_SIZE.y),
+            }
+            .spawn(&mut cmd);
+        }
+    }
+}
+
+/// Removes the joints holding basements which are gone
+fn remove_orphaned_basement_anchors(
+    mut cmd: Commands,
+    anchors: Query<(Entity, &BasementAnchor)>,
+    buildings: Query<(), With<Building>>,
+) {
+    for (entity, anchor) in &anchors {
+        if !buildings.contains(anchor.basement) {
+            cmd.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Removes damper masses whose building is gone
+fn remove_orphaned_dampers(
+    mut cmd: Commands,
+    dampers: Query<(Entity, &DamperMass)>,
+    buildings: Query<(), With<Building>>,
+) {
+    for (entity, damper) in &dampers {
+        if buildings.contains(damper.building) {
+            continue;
+        }
+
+        cmd.entity(damper.joint).despawn_recursive();
+        cmd.entity(entity).despawn_recursive();
+    }
+}
+
+/// ctrl+z
+fn undo_requested(keys: Res<ButtonInput<KeyCode>>) -> bool {
+    keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight])
+        && !keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight])
+        && keys.just_pressed(KeyCode::KeyZ)
+}
+
+/// ctrl+y or ctrl+shift+z
+fn redo_requested(keys: Res<ButtonInput<KeyCode>>) -> bool {
+    keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight])
+        && (keys.just_pressed(KeyCode::KeyY)
+            || keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight])
+                && keys.just_pressed(KeyCode::KeyZ))
+}
+
+/// Used to only run systems when currently building op is selected
+fn only_for_building_op(build_op: Res<SelectedBuildOps>) -> bool {
+    matches!(build_op.selected, BuildOps::Building)
+}
+
+/// Used to only run systems when currently joint op is selected
+fn only_for_joint_op(build_op: Res<SelectedBuildOps>) -> bool {
+    matches!(build_op.selected, BuildOps::Joint)
+}
+
+/// Looks for a burning building under the cursor
+fn update_extinguish_preview(
+    builders: Query<&GlobalTransform, With<CursorBuilder>>,
+    spatial_query: SpatialQuery,
+    burning: Query<(&Building, &GlobalTransform), With<OnFire>>,
+    mut previews: Query<&mut ExtinguishPreview>,
+    mut gizmos: Gizmos,
+) {
+    let mut preview = previews.single_mut();
+    preview.target = None;
+
+    if builders.is_empty() {
+        return;
+    }
+
+    let projected = match spatial_query.project_point(
+        builders.single().translation().xy(),
+        true,
+        SpatialQueryFilter::from_mask(Layers::Building),
+    ) {
+        Some(x) if x.is_inside => x,
+        _ => return,
+    };
+
+    let (building, transform) = match burning.get(projected.entity) {
+        Ok(x) => x,
+        Err(_) => return,
+    };
+
+    preview.target = Some(projected.entity);
+
+    gizmos.rect_2d(
+        transform.translation().xy(),
+        transform.right().xy().to_angle(),
+        building.size,
+        DEEP_SKY_BLUE,
+    );
+}
+
+/// Puts out the previewed fire on click, if the player can pay for it
+fn extinguish_on_click(
+    mut cmd: Commands,
+    mouse: Res<ButtonInput<MouseButton>>,
+    previews: Query<&ExtinguishPreview>,
+    mut player: ResMut<Player>,
+) {
+    if !mouse.just_released(MouseButton::Left) || player.money < EXTINGUISH_COST {
+        return;
+    }
+
+    let target = match previews.single().target {
+        Some(x) => x,
+        None => return,
+    };
+
+    player.money -= EXTINGUISH_COST;
+    cmd.entity(target).remove::<OnFire>();
+}
+
+/// Looks for a building under the cursor to repair
+fn update_repair_preview(
+    builders: Query<&GlobalTransform, With<CursorBuilder>>,
+    spatial_query: SpatialQuery,
+    buildings: Query<(&Building, &GlobalTransform, Has<Repairing>), Without<UnderConstruction>>,
+    mut previews: Query<&mut RepairPreview>,
+    mut gizmos: Gizmos,
+) {
+    let mut preview = previews.single_mut();
+    preview.target = None;
+    preview.active = false;
+    preview.cost = repair_cost(has_storage(buildings.iter().map(|(b, _, _)| b)));
+
+    if builders.is_empty() {
+        return;
+    }
+
+    let projected = match spatial_query.project_point(
+        builders.single().translation().xy(),
+        true,
+        SpatialQueryFilter::from_mask(Layers::Building),
+    ) {
+        Some(x) if x.is_inside => x,
+        _ => return,
+    };
+
+    let (building, transform, active) = match buildings.get(projected.entity) {
+        Ok(x) => x,
+        Err(_) => return,
+    };
+
+    preview.target = Some(projected.entity);
+    preview.active = active;
+
+    gizmos.rect_2d(
+        transform.translation().xy(),
+        transform.right().xy().to_angle(),
+        building.size,
+        ORANGE,
+    );
+}
+
+/// Starts repairing the previewed building on click, clicking again stops it
+fn repair_on_click(
+    mut cmd: Commands,
+    mouse: Res<ButtonInput<MouseButton>>,
+    previews: Query<&RepairPreview>,
+    player: Res<Player>,
+) {
+    if !mouse.just_released(MouseButton::Left) {
+        return;
+    }
+
+    let preview = previews.single();
+    let target = match preview.target {
+        Some(x) => x,
+        None => return,
+    };
+
+    if preview.active {
+        cmd.entity(target).remove::<Repairing>();
+    } else if player.money >= preview.cost {
+        cmd.entity(target).insert(Repairing { owed: 0.0 });
+    }
+}
+
+/// Jacks repairing buildings back upright and patches them up while the player pays for it.
+/// Once a building is level and healthy again, its snapped joints are rebuilt.
+fn progress_repairs(
+    mut cmd: Commands,
+    mut buildings: Query<(
+        Entity,
+        &mut Building,
+        &mut Repairing,
+        &GlobalTransform,
+        &AngularVelocity,
+        &Inertia,
+    )>,
+    frayed_joints: Query<(Entity, &FrayedJoint)>,
+    transforms: Query<&GlobalTransform>,
+    // a storage which is repaired itself can't help
+    storages: Query<&Building, (Without<Repairing>, Without<UnderConstruction>)>,
+    mut player: ResMut<Player>,
+    time: Res<Time>,
+) {
+    let cost = repair_cost(has_storage(storages.iter()));
+
+    for (entity, mut building, mut repairing, transform, angular_velocity, inertia) in
+        buildings.iter_mut()
+    {
+        repairing.owed += cost as f32 * time.delta_seconds();
+        let due = repairing.owed as i64;
+
+        if player.money < due {
+            // stalls until there is money again
+            continue;
+        }
+        player.money -= due;
+        repairing.owed -= due as f32;
+
+        let angle = transform.right().xy().to_angle();
+        let torque = -(angle * REPAIR_STIFFNESS + angular_velocity.0 * REPAIR_DAMPING) * inertia.0;
+        cmd.entity(entity)
+            .insert(ExternalTorque::new(torque).with_persistence(false));
+
+        building.health = (building.health + REPAIR_HEALTH_PER_SECOND * time.delta_seconds())
+            .min(BUILDING_HEALTH);
+
+        if angle.abs() > REPAIR_LEVEL_ANGLE
+            || angular_velocity.0.abs() > REPAIR_LEVEL_ANGLE
+            || building.health < BUILDING_HEALTH
+        {
+            continue;
+        }
+
+        cmd.entity(entity).remove::<Repairing>();
+
+        for (frayed_entity, frayed) in &frayed_joints {
+            if frayed.entity1 != entity && frayed.entity2 != entity {
+                continue;
+            }
+
+            let (t1, t2) = match (
+                transforms.get(frayed.entity1),
+                transforms.get(frayed.entity2),
+            ) {
+                (Ok(t1), Ok(t2)) => (t1, t2),
+                _ => continue,
+            };
+
+            let point1 = t1.translation().xy() + t1.right().xy().rotate(frayed.local_anchor1);
+            let point2 = t2.translation().xy() + t2.right().xy().rotate(frayed.local_anchor2);
+            let middle = point1.lerp(point2, 0.5);
+
+            let plan = JointPlan {
+                kind: frayed.kind,
+                entity1: frayed.entity1,
+                local_anchor1: frayed.local_anchor1,
+                middle1: Vec2::from_angle(-t1.right().xy().to_angle())
+                    .rotate(middle - t1.translation().xy()),
+                entity2: frayed.entity2,
+                local_anchor2: frayed.local_anchor2,
+                middle2: Vec2::from_angle(-t2.right().xy().to_angle())
+                    .rotate(middle - t2.translation().xy()),
+                length: point1.distance(point2),
+                anchor: frayed.anchor,
+            };
+
+            let cost = plan.cost();
+            if cost > player.money {
+                continue;
+            }
+            player.money -= cost;
+
+            plan.spawn(&mut cmd);
+            cmd.entity(frayed_entity).despawn_recursive();
+        }
+    }
+}
+
+/// Marks buildings under repair with a jack below them
+fn display_repairs(
+    buildings: Query<(&Building, &GlobalTransform), With<Repairing>>,
+    mut gizmos: Gizmos,
+) {
+    for (building, transform) in &buildings {
+        let bottom = transform.translation().xy()
+            + transform
+                .right()
+                .xy()
+                .rotate(Vec2::new(0.0, -building.size.y / 2.0));
+
+        gizmos.rect_2d(
+            transform.translation().xy(),
+            transform.right().xy().to_angle(),
+            building.size + Vec2::splat(6.0),
+            ORANGE,
+        );
+        gizmos.line_2d(bottom, bottom + Vec2::new(0.0, -20.0), ORANGE);
+        gizmos.line_2d(
+            bottom + Vec2::new(-12.0, -20.0),
+            bottom + Vec2::new(12.0, -20.0),
+            ORANGE,
+        );
+    }
+}
+
+/// Looks for a ground floor standing directly on the plates under the cursor
+fn update_isolator_preview(
+    builders: Query<&GlobalTransform, With<CursorBuilder>>,
     spatial_query: SpatialQuery,
+    collisions: Res<Collisions>,
+    buildings: Query<(&Building, &GlobalTransform), Without<UnderConstruction>>,
+    plates: Query<(), With<Plate>>,
+    mut previews: Query<&mut IsolatorPreview>,
     mut gizmos: Gizmos,
 ) {
     let mut preview = previews.single_mut();
+    preview.target = None;
 
-    let window = windows.single();
-    let (camera, camera_transform) = cameras.single();
+    if builders.is_empty() {
+        return;
+    }
 
-    let cursor_position = match window.cursor_position() {
-        Some(x) => x,
-        None => return,
+    let projected = match spatial_query.project_point(
+        builders.single().translation().xy(),
+        true,
+        SpatialQueryFilter::from_mask(Layers::Building),
+    ) {
+        Some(x) if x.is_inside => x,
+        _ => return,
     };
 
-    let cursor_world_position = match camera.viewport_to_world_2d(camera_transform, cursor_position)
-    {
+    let (building, transform) = match buildings.get(projected.entity) {
+        Ok(x) => x,
+        Err(_) => return,
+    };
+
+    let on_plates = collisions
+        .collisions_with_entity(projected.entity)
+        .any(|contacts| {
+            contacts.during_current_frame
+                && (plates.contains(contacts.entity1) || plates.contains(contacts.entity2))
+        });
+    if !on_plates {
+        return;
+    }
+
+    preview.target = Some(projected.entity);
+
+    let angle = transform.right().xy().to_angle();
+    let pad = transform.translation().xy()
+        + transform.right().xy().rotate(Vec2::new(
+            0.0,
+            -building.size.y / 2.0 + ISOLATOR_HEIGHT / 2.0,
+        ));
+
+    gizmos.rect_2d(transform.translation().xy(), angle, building.size, GRAY);
+    gizmos.rect_2d(
+        pad,
+        angle,
+        Vec2::new(building.size.x, ISOLATOR_HEIGHT),
+        DARK_SLATE_BLUE,
+    );
+}
+
+/// Jacks the previewed building, everything resting on its top and everything joined to it up
+/// and slides an isolator pad underneath, if the player can pay for it. Buildings tied to the
+/// plates, the ground or a basement stay where they are, nothing is lifted then.
+fn isolate_on_click(
+    mut cmd: Commands,
+    mouse: Res<ButtonInput<MouseButton>>,
+    previews: Query<&IsolatorPreview>,
+    (collisions, joints): (Res<Collisions>, Query<&BuildingJoint>),
+    mut buildings: Query<(&Building, &Transform, &mut Position)>,
+    mut dampers: Query<(&DamperMass, &mut Position), Without<Building>>,
+    mut player: ResMut<Player>,
+) {
+    if !mouse.just_released(MouseButton::Left) || player.money < ISOLATOR_COST {
+        return;
+    }
+
+    let target = match previews.single().target {
         Some(x) => x,
         None => return,
     };
 
-    if let Some(e) = preview.entity_start {
-        let transform = match transforms.get(e) {
-            Ok(x) => x,
-            Err(_) => return,
-        };
+    let (building_size, transform) = match buildings.get(target) {
+        Ok((building, transform, _)) => (building.size, *transform),
+        Err(_) => return,
+    };
 
-        let point =
-            transform.translation().xy() + transform.right().xy().rotate(preview.local_start);
+    // everything stacked on the building or joined to it gets lifted with it
+    let mut lifted = vec![target];
+    let mut index = 0;
+    while index < lifted.len() {
+        let below = lifted[index];
+        index += 1;
 
-        gizmos.circle_2d(point, 8.0, PURPLE);
+        for contacts in collisions.collisions_with_entity(below) {
+            // the normals point out of the building they belong to
+            let (other, on_top) = if contacts.entity1 == below {
+                let on_top = contacts
+                    .manifolds
+                    .iter()
+                    .any(|manifold| manifold.normal1.y > TOP_FACE_NORMAL);
+                (contacts.entity2, on_top)
+            } else {
+                let on_top = contacts
+                    .manifolds
+                    .iter()
+                    .any(|manifold| manifold.normal2.y > TOP_FACE_NORMAL);
+                (contacts.entity1, on_top)
+            };
 
-        gizmos.arrow_2d(point, cursor_world_position, WHITE);
+            if contacts.during_current_frame
+                && on_top
+                && buildings.contains(other)
+                && !lifted.contains(&other)
+            {
+                lifted.push(other);
+            }
+        }
 
-        preview.length = cursor_world_position.distance(point);
-    } else {
-        preview.length = 0.0;
+        for joint in &joints {
+            let other = if joint.entity1 == below {
+                joint.entity2
+            } else if joint.entity2 == below {
+                joint.entity1
+            } else {
+                continue;
+            };
+
+            match joint.anchor {
+                JointAnchor::Building if !lifted.contains(&other) => lifted.push(other),
+                JointAnchor::Building => {}
+                // tied down, lifting it would only hang it on the joint
+                JointAnchor::Plate | JointAnchor::Ground | JointAnchor::Basement(_) => return,
+            }
+        }
     }
 
-    let targeting_building = if let Some(projected) = spatial_query.project_point(
-        cursor_world_position,
-        true,
-        SpatialQueryFilter::from_mask(Layers::Building),
-    ) {
-        projected.is_inside
-    } else {
-        false
-    };
+    player.money -= ISOLATOR_COST;
 
-    gizmos.circle_2d(
-        cursor_world_position,
-        8.0,
-        if targeting_building {
-            GREEN
-        } else if preview.entity_start.is_some() {
-            ORANGE
-        } else {
-            RED
-        },
-    );
+    // the physics position, the transform follows after the next step
+    for entity in &lifted {
+        if let Ok((_, _, mut position)) = buildings.get_mut(*entity) {
+            position.y += ISOLATOR_HEIGHT;
+        }
+    }
+    for (damper, mut position) in &mut dampers {
+        if lifted.contains(&damper.building) {
+            position.y += ISOLATOR_HEIGHT;
+        }
+    }
+
+    spawn_isolator(&mut cmd, target, building_size, &transform);
 }
 
-/// display joints
-fn display_joints(
-    joints: Query<&DistanceJoint, With<BuildingJoint>>,
-    transforms: Query<&GlobalTransform>,
-    mut gizmos: Gizmos,
-) {
-    for joint in &joints {
-        let t1 = match transforms.get(joint.entity1) {
-            Ok(t) => t,
-            Err(_) => continue,
-        };
+/// Puts a pad under a ground floor which was just lifted off its plate.
+/// The transform is the one of the building before it was lifted.
+fn spawn_isolator(cmd: &mut Commands, target: Entity, building_size: Vec2, transform: &Transform) {
+    let size = Vec2::new(building_size.x, ISOLATOR_HEIGHT);
+    let mut pad_transform = *transform;
+    pad_transform.translation.y += ISOLATOR_HEIGHT;
+    pad_transform.translation += transform.rotation.mul_vec3(Vec3::new(
+        0.0,
+        -(building_size.y + ISOLATOR_HEIGHT) / 2.0,
+        0.0,
+    ));
 
-        let t2 = match transforms.get(joint.entity2) {
-            Ok(t) => t,
-            Err(_) => continue,
-        };
+    let joint = cmd.spawn_empty().id();
+    let pad = cmd
+        .spawn((
+            RigidBody::Dynamic,
+            Collider::rectangle(size.x, size.y),
+            ColliderDensity(ISOLATOR_DENSITY),
+            Friction::new(ISOLATOR_FRICTION).with_combine_rule(CoefficientCombine::Min),
+            Restitution::ZERO,
+            isolator_layers(),
+            SpriteBundle {
+                sprite: Sprite {
+                    color: DARK_SLATE_BLUE.into(),
+                    custom_size: Some(size),
+                    ..default()
+                },
+                transform: pad_transform,
+                ..default()
+            },
+        ))
+        .id();
 
-        let point1 = t1.translation().xy() + t1.right().xy().rotate(joint.local_anchor_1());
-        let point2 = t2.translation().xy() + t2.right().xy().rotate(joint.local_anchor_2());
+    cmd.entity(joint).insert(
+        FixedJoint::new(target, pad)
+            .with_local_anchor_1(Vec2::new(0.0, -building_size.y / 2.0))
+            .with_local_anchor_2(Vec2::new(0.0, ISOLATOR_HEIGHT / 2.0))
+            .with_compliance(ISOLATOR_COMPLIANCE)
+            .with_linear_velocity_damping(ISOLATOR_DAMPING)
+            .with_angular_velocity_damping(ISOLATOR_DAMPING),
+    );
+    cmd.entity(pad).insert(IsolatorPad {
+        building: target,
+        joint,
+    });
+    cmd.entity(target).insert(isolated_building_layers());
+}
 
-        gizmos.circle_2d(point1, 8.0, WHITE);
-        gizmos.circle_2d(point1, 7.0, PURPLE);
-        gizmos.circle_2d(point2, 8.0, WHITE);
-        gizmos.circle_2d(point2, 7.0, PURPLE);
+/// Removes isolator pads whose building is gone
+fn remove_orphaned_isolators(
+    mut cmd: Commands,
+    pads: Query<(Entity, &IsolatorPad)>,
+    buildings: Query<(), With<Building>>,
+) {
+    for (entity, pad) in &pads {
+        if buildings.contains(pad.building) {
+            continue;
+        }
 
-        gizmos.line_2d(point1, point2, WHITE);
-        gizmos.line_2d(
-            point1 + Vec2::new(1.0, -1.0),
-            point2 + Vec2::new(1.0, -1.0),
-            PURPLE,
-        );
+        cmd.entity(pad.joint).despawn_recursive();
+        cmd.entity(entity).despawn_recursive();
     }
 }
 
-/// Used to only run systems when currently building op is selected
-fn only_for_building_op(build_op: Res<SelectedBuildOps>) -> bool {
-    matches!(build_op.selected, BuildOps::Building)
+/// Used to only run systems when currently isolate op is selected
+fn only_for_isolate_op(build_op: Res<SelectedBuildOps>) -> bool {
+    matches!(build_op.selected, BuildOps::Isolate)
 }
 
-/// Used to only run systems when currently building op is selected
-fn only_for_joint_op(build_op: Res<SelectedBuildOps>) -> bool {
-    matches!(build_op.selected, BuildOps::Joint)
+/// Used to only run systems when currently repair op is selected
+fn only_for_repair_op(build_op: Res<SelectedBuildOps>) -> bool {
+    matches!(build_op.selected, BuildOps::Repair)
+}
+
+/// Used to only run systems when currently extinguish op is selected
+fn only_for_extinguish_op(build_op: Res<SelectedBuildOps>) -> bool {
+    matches!(build_op.selected, BuildOps::Extinguish)
+}
+
+/// Used to only run systems when currently demolish op is selected
+fn only_for_demolish_op(build_op: Res<SelectedBuildOps>) -> bool {
+    matches!(build_op.selected, BuildOps::Demolish)
 }
 
 /// Plugin for everything related to buildings
@@ -929,6 +4565,13 @@ pub struct BuildingsPlugin;
 impl Plugin for BuildingsPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<PlaceBuildingEvent>()
+            .add_event::<JointBroken>()
+            .add_event::<BuildingDamaged>()
+            .add_event::<BuildingCollapsed>()
+            .add_event::<BuildActionDone>()
+            .init_resource::<Blueprints>()
+            .init_resource::<BuildHistory>()
+            .init_resource::<StressView>()
             .insert_resource(SelectedBuildOps {
                 selected: BuildOps::Building,
             })
@@ -937,27 +4580,664 @@ impl Plugin for BuildingsPlugin {
                 Update,
                 (
                     update_selected_build_op,
+                    update_cursor_builder,
                     (
-                        update_cursor_builder,
                         (
+                            hold_preview_building,
+                            dig_preview_building,
+                            resize_preview_building,
                             update_preview_building,
                             display_preview_building,
                             maybe_send_place_building_event,
                         )
                             .chain(),
+                        cancel_construction_on_right_click,
                     )
                         .run_if(only_for_building_op),
-                    outline_buildings_system,
+                    (
+                        update_building_stress,
+                        damage_hit_buildings,
+                        collapse_destroyed_buildings,
+                        toggle_stress_view,
+                        outline_buildings_system,
+                        update_stress_label,
+                    )
+                        .chain(),
                     // outline_chimneys_system,
                     display_preview_joint.run_if(only_for_joint_op),
+                    (
+                        (update_demolish_preview, demolish_on_click)
+                            .chain()
+                            .run_if(only_for_demolish_op),
+                        (update_extinguish_preview, extinguish_on_click)
+                            .chain()
+                            .run_if(only_for_extinguish_op),
+                        (update_repair_preview, repair_on_click)
+                            .chain()
+                            .run_if(only_for_repair_op),
+                        progress_repairs,
+                        display_repairs,
+                        (update_isolator_preview, isolate_on_click)
+                            .chain()
+                            .run_if(only_for_isolate_op),
+                        remove_orphaned_isolators,
+                        (
+                            embed_basements,
+                            anchor_to_basements,
+                            remove_orphaned_basement_anchors,
+                        ),
+                    ),
+                    update_build_tool_label,
+                    update_joint_tool_label,
+                    update_piece_queue_label,
                     display_joints,
-                    update_cursor_text,
+                    display_frayed_joints,
+                    display_dampers,
+                    remove_orphaned_dampers,
+                    update_cracks,
+                    update_debris,
+                    display_blueprints,
+                    remove_last_blueprint,
+                    (
+                        record_build_actions,
+                        undo_build_action.run_if(undo_requested),
+                        redo_build_action.run_if(redo_requested),
+                    )
+                        .chain(),
+                    (
+                        (
+                            update_joint_force::<DistanceJoint>,
+                            update_joint_force::<FixedJoint>,
+                            update_joint_force::<RevoluteJoint>,
+                        ),
+                        break_overloaded_joints,
+                        handle_joint_broken,
+                    )
+                        .chain(),
+                    update_cursor_text.after(update_cursor_builder),
                 ),
             )
             .add_systems(
                 FixedUpdate,
                 check_building_clicked_for_joint.run_if(only_for_joint_op),
             )
-            .add_systems(FixedUpdate, handle_place_building_event);
+            .add_systems(
+                FixedUpdate,
+                (
+                    handle_place_building_event,
+                    progress_construction,
+                    build_blueprints,
+                ),
+            );
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::time::Duration;
+
+    use bevy::{audio::AudioSource, ecs::system::RunSystemOnce, time::TimeUpdateStrategy};
+
+    use super::*;
+    use crate::earthquake::{EarthquakePlugin, EarthquakeSchedule};
+
+    /// Fixed physics step, the sample interval of the recording
+    const STEP: f32 = 0.02;
+    /// Steps to let the towers settle before the quake and to sway afterwards
+    const SETTLE_STEPS: usize = 100;
+    /// Floors of a test tower
+    const FLOORS: usize = 8;
+    /// Floors of a tower too tall to carry its own weight if loads did damage
+    const TALL_FLOORS: usize = 20;
+    /// Size of a floor of a test tower
+    const FLOOR_SIZE: Vec2 = Vec2::new(80.0, 60.0);
+
+    /// Headless app stepping the physics by exactly one fixed step per update
+    pub(crate) fn physics_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            TransformPlugin,
+            HierarchyPlugin,
+            PhysicsPlugins::new(FixedPostUpdate),
+        ))
+        .init_resource::<bevy::scene::SceneSpawner>()
+        .insert_resource(Time::<Fixed>::from_seconds(STEP as f64))
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+            STEP,
+        )));
+        app
+    }
+
+    /// Spawns a plate which only moves when the test moves it
+    fn spawn_plate(world: &mut World, x: f32) -> Entity {
+        world
+            .spawn((
+                RigidBody::Kinematic,
+                Collider::rectangle(600.0, 50.0),
+                plates_layers(),
+                Friction::new(0.8),
+                TransformBundle::from_transform(Transform::from_xyz(x, -25.0, 0.0)),
+            ))
+            .id()
+    }
+
+    /// Spawns a finished brick floor, a damper gets its mass right away
+    fn spawn_floor(world: &mut World, position: Vec2, variant: BuildingVariants) -> Entity {
+        let building = Building {
+            size: FLOOR_SIZE,
+            cost: 0,
+            material: BuildingMaterial::Brick,
+            variant,
+            health: BUILDING_HEALTH,
+        };
+        let transform = Transform::from_translation(position.extend(0.0));
+        let entity = world
+            .spawn((
+                BuildingStress::default(),
+                RigidBody::Dynamic,
+                Collider::rectangle(FLOOR_SIZE.x, FLOOR_SIZE.y),
+                building_layers(),
+                ColliderDensity(building.material.density()),
+                Friction::new(building.material.friction()),
+                TransformBundle::from_transform(transform),
+            ))
+            .id();
+
+        if building.variant == BuildingVariants::Damper {
+            spawn_damper(&mut world.commands(), entity, &building, &transform);
+            world.flush();
+        }
+        world.entity_mut(entity).insert(building);
+        entity
+    }
+
+    /// Headless app with the plates of the game, shaken by `earthquake()` following the schedule.
+    /// The same seed shakes the same plates the same way.
+    pub(crate) fn quake_app(seed: u64, schedule: EarthquakeSchedule) -> App {
+        let mut app = physics_app();
+        app.add_plugins((AssetPlugin::default(), EarthquakePlugin))
+            .init_asset::<Image>()
+            .init_asset::<Font>()
+            .init_asset::<AudioSource>()
+            .init_resource::<ButtonInput<KeyCode>>();
+
+        // spawns the plates and starts loading the schedule of the game, which is replaced
+        app.update();
+        let schedule = app
+            .world_mut()
+            .resource_mut::<Assets<EarthquakeSchedule>>()
+            .add(schedule);
+        app.insert_resource(EarthquakeTimer::new(seed, schedule));
+        app
+    }
+
+    /// Spawns a tower of floors joined by beams standing on the base,
+    /// the floor with the given index gets a damper
+    fn spawn_tower(
+        world: &mut World,
+        base: Vec2,
+        floors: usize,
+        damper: Option<usize>,
+    ) -> Vec<Entity> {
+        let mut tower: Vec<Entity> = vec![];
+        for i in 0..floors {
+            let variant = if damper == Some(i) {
+                BuildingVariants::Damper
+            } else {
+                BuildingVariants::Default
+            };
+            let entity = spawn_floor(
+                world,
+                base + Vec2::Y * FLOOR_SIZE.y * (i as f32 + 0.5),
+                variant,
+            );
+
+            if let Some(&below) = tower.last() {
+                world.spawn(
+                    FixedJoint::new(below, entity)
+                        .with_local_anchor_1(Vec2::Y * FLOOR_SIZE.y / 2.0)
+                        .with_local_anchor_2(Vec2::NEG_Y * FLOOR_SIZE.y / 2.0),
+                );
+            }
+            tower.push(entity);
+        }
+        tower
+    }
+
+    /// The largest tilt of any floor in radians
+    fn tilt(app: &App, floors: &[Entity]) -> f32 {
+        floors
+            .iter()
+            .map(|floor| {
+                let transform = app.world().get::<Transform>(*floor).unwrap();
+                transform.right().xy().to_angle().abs()
+            })
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn spring_stretches_under_a_building() {
+        let mut app = physics_app();
+        let world = app.world_mut();
+        let hook = world
+            .spawn((
+                RigidBody::Static,
+                TransformBundle::from_transform(Transform::from_xyz(0.0, 200.0, 0.0)),
+            ))
+            .id();
+        let building = world
+            .spawn((
+                RigidBody::Dynamic,
+                Collider::rectangle(REFERENCE_SIZE.x, REFERENCE_SIZE.y),
+                ColliderDensity(BuildingMaterial::Brick.density()),
+                TransformBundle::from_transform(Transform::from_xyz(0.0, 100.0, 0.0)),
+            ))
+            .id();
+        JointPlan {
+            kind: JointKind::Spring,
+            entity1: hook,
+            local_anchor1: Vec2::ZERO,
+            middle1: Vec2::ZERO,
+            entity2: building,
+            local_anchor2: Vec2::ZERO,
+            middle2: Vec2::ZERO,
+            length: 100.0,
+            anchor: JointAnchor::Ground,
+        }
+        .spawn(&mut world.commands());
+        world.flush();
+
+        for _ in 0..10 * SETTLE_STEPS {
+            app.update();
+        }
+
+        let y = app
+            .world()
+            .get::<Transform>(building)
+            .unwrap()
+            .translation
+            .y;
+        let stretch = 100.0 - y;
+        assert!(
+            (5.0..30.0).contains(&stretch),
+            "spring stretched by {stretch} pixels"
+        );
+    }
+
+    /// Headless app which also damages buildings
+    fn damage_app() -> App {
+        let mut app = physics_app();
+        app.add_event::<BuildingDamaged>().add_systems(
+            Update,
+            (update_building_stress, damage_hit_buildings).chain(),
+        );
+        app
+    }
+
+    #[test]
+    fn standing_tower_takes_no_damage() {
+        let mut app = damage_app();
+        let world = app.world_mut();
+        spawn_plate(world, 0.0);
+        let tower = spawn_tower(world, Vec2::ZERO, TALL_FLOORS, None);
+
+        for _ in 0..5 * SETTLE_STEPS {
+            app.update();
+        }
+
+        for floor in tower {
+            let building = app.world().get::<Building>(floor).unwrap();
+            assert_eq!(building.health, BUILDING_HEALTH);
+        }
+    }
+
+    #[test]
+    fn dropped_building_is_damaged() {
+        let mut app = damage_app();
+        let world = app.world_mut();
+        spawn_plate(world, 0.0);
+        let floor = spawn_floor(
+            world,
+            Vec2::new(0.0, 150.0 + FLOOR_SIZE.y / 2.0),
+            BuildingVariants::Default,
+        );
+
+        for _ in 0..5 * SETTLE_STEPS {
+            app.update();
+        }
+
+        let building = app.world().get::<Building>(floor).unwrap();
+        assert!(building.health < BUILDING_HEALTH);
+    }
+
+    /// Clicks the ground floor of a tower with the isolator tool, the player has just enough money
+    fn isolate(world: &mut World, ground_floor: Entity) {
+        let mut mouse = ButtonInput::<MouseButton>::default();
+        mouse.press(MouseButton::Left);
+        mouse.release(MouseButton::Left);
+        world.insert_resource(mouse);
+        world.insert_resource(Player {
+            money: ISOLATOR_COST,
+        });
+        world.spawn(IsolatorPreview {
+            target: Some(ground_floor),
+        });
+        world.run_system_once(isolate_on_click);
+    }
+
+    /// Heights of the floors of a tower
+    fn heights(world: &World, floors: &[Entity]) -> Vec<f32> {
+        floors
+            .iter()
+            .map(|floor| world.get::<Position>(*floor).unwrap().y)
+            .collect()
+    }
+
+    #[test]
+    fn isolator_lifts_only_the_tower_on_the_pad() {
+        let mut app = quake_app(0, shear_wave(0.0));
+        let world = app.world_mut();
+        let tower = spawn_tower(world, TOWER_BASE, FLOORS, None);
+        let neighbour = spawn_tower(world, TOWER_BASE + Vec2::X * FLOOR_SIZE.x, FLOORS + 2, None);
+        for _ in 0..SETTLE_STEPS {
+            app.update();
+        }
+
+        let world = app.world_mut();
+        let before = heights(world, &tower);
+        let neighbour_before = heights(world, &neighbour);
+        isolate(world, tower[0]);
+
+        assert_eq!(world.resource::<Player>().money, 0);
+        for (before, after) in before.iter().zip(heights(world, &tower)) {
+            assert_eq!(
+                after,
+                before + ISOLATOR_HEIGHT,
+                "the tower should be lifted"
+            );
+        }
+        assert_eq!(
+            heights(world, &neighbour),
+            neighbour_before,
+            "the tower beside it should stay on the plates"
+        );
+    }
+
+    #[test]
+    fn isolator_refuses_towers_tied_down() {
+        let mut app = quake_app(0, shear_wave(0.0));
+        let world = app.world_mut();
+        let tower = spawn_tower(world, TOWER_BASE, FLOORS, None);
+        let neighbour = spawn_tower(world, TOWER_BASE + Vec2::X * 300.0, FLOORS, None);
+        for _ in 0..SETTLE_STEPS {
+            app.update();
+        }
+
+        // the tower is joined to its neighbour, which is tied to the ground
+        let world = app.world_mut();
+        let ground = world
+            .spawn((RigidBody::Static, TransformBundle::default()))
+            .id();
+        for (entity1, entity2, anchor) in [
+            (
+                tower[FLOORS - 1],
+                neighbour[FLOORS - 1],
+                JointAnchor::Building,
+            ),
+            (ground, neighbour[FLOORS - 1], JointAnchor::Ground),
+        ] {
+            JointPlan {
+                kind: JointKind::Rope,
+                entity1,
+                local_anchor1: Vec2::ZERO,
+                middle1: Vec2::ZERO,
+                entity2,
+                local_anchor2: Vec2::ZERO,
+                middle2: Vec2::ZERO,
+                length: 300.0,
+                anchor,
+            }
+            .spawn(&mut world.commands());
+        }
+        world.flush();
+
+        let before = heights(world, &tower);
+        isolate(world, tower[0]);
+
+        assert_eq!(world.resource::<Player>().money, ISOLATOR_COST);
+        assert_eq!(heights(world, &tower), before);
+    }
+
+    /// Peak sideways speed of a ground floor on the real plates, isolated by the player or not
+    fn quake_sway(seed: u64, isolated: bool) -> f32 {
+        let mut app = quake_app(seed, shear_wave(QUAKE_INTENSITIES[1]));
+        let tower = spawn_tower(app.world_mut(), TOWER_BASE, FLOORS, None);
+        for _ in 0..SETTLE_STEPS {
+            app.update();
+        }
+        if isolated {
+            isolate(app.world_mut(), tower[0]);
+        }
+
+        let mut peak: f32 = 0.0;
+        for _ in 0..(QUAKE_SECONDS / STEP) as usize {
+            app.update();
+            let velocity = app.world().get::<LinearVelocity>(tower[0]).unwrap();
+            peak = peak.max(velocity.x.abs());
+        }
+        peak
+    }
+
+    #[test]
+    fn isolator_steadies_a_tower() {
+        let mut plain = 0.0;
+        let mut isolated = 0.0;
+        for seed in QUAKE_SEEDS {
+            plain += quake_sway(seed, false);
+            isolated += quake_sway(seed, true);
+        }
+
+        assert!(
+            isolated < 0.5 * plain,
+            "isolated towers reached {isolated} px/s in total but the plain ones only {plain} px/s"
+        );
+    }
+
+    #[test]
+    fn basement_rests_on_its_pit_floor() {
+        let mut app = physics_app();
+        app.add_systems(Update, embed_basements);
+
+        let world = app.world_mut();
+        let plate = spawn_plate(world, 0.0);
+        world.entity_mut(plate).insert(Plate {
+            index: 0,
+            home: Vec2::new(0.0, -25.0),
+        });
+        // the plate has to be known to spatial queries before the basement is dug
+        for _ in 0..SETTLE_STEPS {
+            app.update();
+        }
+
+        let size = Vec2::new(FLOOR_SIZE.x, 100.0);
+        let basement = app
+            .world_mut()
+            .spawn((
+                Building {
+                    size,
+                    cost: 0,
+                    material: BuildingMaterial::Brick,
+                    variant: BuildingVariants::Basement(BasementRoom::Storage),
+                    health: BUILDING_HEALTH,
+                },
+                RigidBody::Dynamic,
+                Collider::rectangle(size.x, size.y),
+                basement_layers(),
+                TransformBundle::from_transform(Transform::from_xyz(0.0, -size.y / 2.0, 0.0)),
+            ))
+            .id();
+
+        for _ in 0..SETTLE_STEPS {
+            app.update();
+        }
+
+        // the plate lets go
+        let world = app.world_mut();
+        let joints: Vec<Entity> = world
+            .query_filtered::<Entity, (With<BasementAnchor>, With<FixedJoint>)>()
+            .iter(world)
+            .collect();
+        assert!(!joints.is_empty());
+        for joint in joints {
+            world.despawn(joint);
+        }
+
+        for _ in 0..5 * SETTLE_STEPS {
+            app.update();
+        }
+
+        let y = app.world().get::<Position>(basement).unwrap().y;
+        assert!(
+            (y + size.y / 2.0).abs() < 1.0,
+            "basement sank to {y} from {}",
+            -size.y / 2.0
+        );
+    }
+
+    #[test]
+    fn basement_bolts_are_free() {
+        let mut app = physics_app();
+        app.add_systems(Update, anchor_to_basements)
+            .init_resource::<BuildHistory>()
+            .insert_resource(Player { money: 0 })
+            .insert_resource(EarthquakeTimer::new(0, Handle::default()));
+        let world = app.world_mut();
+        spawn_plate(world, 0.0);
+        let basement = spawn_floor(
+            world,
+            Vec2::Y * FLOOR_SIZE.y / 2.0,
+            BuildingVariants::Basement(BasementRoom::Storage),
+        );
+        let building = spawn_floor(
+            world,
+            Vec2::Y * FLOOR_SIZE.y * 1.5,
+            BuildingVariants::Default,
+        );
+        world.entity_mut(building).insert(UnderConstruction {
+            progress: Timer::from_seconds(1.0, TimerMode::Once),
+            paid: 0,
+        });
+        world.get_mut::<Building>(building).unwrap().cost = 500;
+        app.update();
+        app.update();
+
+        // finishing the building bolts it to the basement
+        app.world_mut()
+            .entity_mut(building)
+            .remove::<UnderConstruction>();
+        app.update();
+        app.update();
+
+        let world = app.world_mut();
+        let bolts: Vec<(Entity, i64)> = world
+            .query::<&BuildingJoint>()
+            .iter(world)
+            .map(|bolt| (bolt.entity2, bolt.cost))
+            .collect();
+        assert_eq!(
+            bolts,
+            vec![(basement, 0)],
+            "the building should be bolted down for free"
+        );
+
+        let time = world.resource::<Time>().elapsed_seconds();
+        world.resource_mut::<BuildHistory>().undo.push(BuildRecord {
+            blueprint: Blueprint::Building(BuildingPlan {
+                position: Vec2::Y * FLOOR_SIZE.y * 1.5,
+                size: FLOOR_SIZE,
+                variant: BuildingVariants::Default,
+                material: BuildingMaterial::Brick,
+            }),
+            entity: building,
+            cost: 500,
+            time,
+            inhabitants: vec![],
+        });
+        world.run_system_once(undo_build_action);
+
+        // undoing refunds the building, the bolt costs nothing
+        assert_eq!(world.resource::<Player>().money, 500);
+    }
+
+    /// Quake shearing the plates sideways, starting from a random plate
+    fn shear_wave(intensity: f32) -> EarthquakeSchedule {
+        let schedule = format!(
+            "(
+                quakes: [(
+                    delay: 5.0,
+                    duration: 4.0,
+                    rumble: 0.08,
+                    intensity: Constant({intensity}),
+                    motion: Wave((
+                        speed: 14.0,
+                        wavelength: 10.0,
+                        attenuation: 0.05,
+                        vertical: 0.0,
+                        horizontal: 1.0,
+                    )),
+                )],
+                speedup: 0.0,
+                min_delay: 10.0,
+            )"
+        );
+        ron::de::from_str(&schedule).unwrap()
+    }
+
+    /// Seeds of the quakes towers are compared under, they start from different plates
+    const QUAKE_SEEDS: std::ops::Range<u64> = 0..4;
+    /// Intensities of the quakes towers are compared under
+    const QUAKE_INTENSITIES: [f32; 2] = [7_000_000.0, 8_000_000.0];
+    /// Seconds until the quake and the sway afterwards are over, before the next quake
+    const QUAKE_SECONDS: f32 = 11.0;
+    /// Where the test towers stand, on top of the plates
+    const TOWER_BASE: Vec2 = Vec2::new(-200.0, -30.0);
+
+    /// The largest tilt of the floors above the ground floor during the seeded quake,
+    /// the ground floor gets a damper if asked for
+    fn quake_tilt(seed: u64, intensity: f32, damper: bool) -> f32 {
+        let mut app = quake_app(seed, shear_wave(intensity));
+        let tower = spawn_tower(app.world_mut(), TOWER_BASE, FLOORS, damper.then_some(0));
+
+        let mut peak: f32 = 0.0;
+        for _ in 0..(QUAKE_SECONDS / STEP) as usize {
+            app.update();
+            peak = peak.max(tilt(&app, &tower[1..]));
+        }
+        peak
+    }
+
+    #[test]
+    fn damper_reduces_tilt() {
+        let mut plain = 0.0;
+        let mut damped = 0.0;
+        for seed in QUAKE_SEEDS {
+            for intensity in QUAKE_INTENSITIES {
+                plain += quake_tilt(seed, intensity, false);
+                damped += quake_tilt(seed, intensity, true);
+            }
+        }
+
+        assert!(
+            plain > 1f32.to_radians(),
+            "the quakes should shake the towers"
+        );
+        // a single quake can go either way, a rocking tower is chaotic
+        assert!(
+            damped < 0.8 * plain,
+            "damped towers tilted {}° in total but the plain ones only {}°",
+            damped.to_degrees(),
+            plain.to_degrees()
+        );
     }
 }
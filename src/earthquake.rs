@@ -1,19 +1,393 @@
 //! Everything related to spawning the plates and randomly moving them
 
+use std::path::Path;
 use std::time::Duration;
 
 use avian2d::prelude::*;
+use bevy::asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext};
 use bevy::audio::Volume;
 use bevy::color::palettes::css::{BLACK, DARK_RED};
 use bevy::{color::palettes::css::BROWN, prelude::*};
-use rand::seq::IteratorRandom;
+use rand::{rngs::StdRng, seq::IteratorRandom, Rng, SeedableRng};
+use serde::Deserialize;
 
 use crate::building::Building;
 use crate::layers::{ground_layers, plates_layers};
 
 /// Plates which will create the earthquake
 #[derive(Component)]
-struct Plate;
+pub struct Plate {
+    /// position from the left, schedules pick plates by it
+    pub index: usize,
+    /// where the plate rests, it is pulled back there after a quake
+    pub home: Vec2,
+}
+
+/// Number of plates side by side, schedules pick plates by their index below it
+const PLATE_COUNT: usize = 20;
+/// How hard a plate is pulled back to its resting place, per second squared
+const PLATE_SETTLE_STIFFNESS: f32 = 40.0;
+/// How much of its movement a plate loses per second while it settles
+const PLATE_SETTLE_DAMPING: f32 = 4.0;
+/// How much neighbouring plates may move apart or together, lets shear waves through
+const PLATE_SHEAR_SLACK: f32 = 8.0;
+/// Shear (S) waves travel slower than the pressure (P) waves
+const S_WAVE_SPEED_FACTOR: f32 = 0.6;
+
+/// The earthquakes of a game in order, loaded from a `.quakes.ron` file in `assets/quakes`
+#[derive(Asset, TypePath, Deserialize)]
+pub struct EarthquakeSchedule {
+    /// the quakes in order, the last one repeats once they are used up
+    quakes: Vec<Quake>,
+    /// every repetition of the last quake comes this many seconds sooner
+    speedup: f32,
+    /// repetitions never come sooner than this
+    min_delay: f32,
+}
+
+impl EarthquakeSchedule {
+    /// the quake with the given number, counted from 0
+    fn quake(&self, number: usize) -> Quake {
+        let last = self.quakes.len() - 1;
+        if number <= last {
+            return self.quakes[number].clone();
+        }
+
+        let mut quake = self.quakes[last].clone();
+        quake.delay = (quake.delay - self.speedup * (number - last) as f32).max(self.min_delay);
+        quake
+    }
+
+    /// an error for values the game can't run with, like negative times or plates which don't exist
+    fn validate(&self) -> Result<(), String> {
+        if self.quakes.is_empty() {
+            return Err("an earthquake schedule needs at least one quake".into());
+        }
+        if self.min_delay < 0.0 {
+            return Err("the min_delay of a schedule can't be negative".into());
+        }
+        self.quakes.iter().try_for_each(Quake::validate)
+    }
+}
+
+/// A single earthquake of a schedule
+#[derive(Deserialize, Clone)]
+pub struct Quake {
+    /// seconds from the start of the previous quake, or the start of the game
+    delay: f32,
+    /// seconds the ground shakes, a recording shakes as long as it is
+    duration: f32,
+    /// seconds between two pushes of the plates, a wave spreads every push over the steps in between
+    rumble: f32,
+    /// how hard the plates are pushed
+    intensity: Intensity,
+    /// how the plates move
+    motion: Motion,
+}
+
+impl Quake {
+    /// an error for values the game can't run with
+    fn validate(&self) -> Result<(), String> {
+        if self.delay < 0.0 || self.duration < 0.0 {
+            return Err("the delay and duration of a quake can't be negative".into());
+        }
+        // kicks alternate and waves are spread by the rumble
+        if self.rumble <= 0.0 {
+            return Err("the rumble of a quake has to be longer than zero".into());
+        }
+
+        match &self.motion {
+            Motion::Kicks { plates, .. } => plates.validate(),
+            Motion::Wave(wave) => {
+                if wave.speed <= 0.0 || wave.wavelength <= 0.0 {
+                    return Err("a wave needs a speed and wavelength above zero".into());
+                }
+                match wave.epicenter {
+                    Some(epicenter) if epicenter >= PLATE_COUNT => Err(format!(
+                        "the epicenter {epicenter} is not one of the {PLATE_COUNT} plates"
+                    )),
+                    _ => Ok(()),
+                }
+            }
+            Motion::Recording(_) => Ok(()),
+        }
+    }
+}
+
+/// How the plates move during a quake
+#[derive(Deserialize, Clone)]
+enum Motion {
+    /// some plates are kicked every rumble
+    Kicks {
+        /// which plates are kicked
+        plates: PlateSelection,
+        /// which way the plates are kicked
+        direction: QuakeDirection,
+    },
+    /// waves travel through the plates from an epicenter
+    Wave(SeismicWave),
+    /// all plates follow a ground motion recording, the intensity is not used
+    Recording(Recording),
+}
+
+/// A ground motion recording (accelerogram) replayed on the plates
+#[derive(Deserialize, Clone)]
+pub struct Recording {
+    /// csv or text file next to the schedule with a `time, acceleration` sample per line
+    file: String,
+    /// plate acceleration in pixels per second squared for one unit of the file, e.g. one g
+    scale: f32,
+    /// shake the plates sideways instead of up and down
+    #[serde(default)]
+    horizontal: bool,
+    /// time and acceleration, read from the file when the schedule is loaded
+    #[serde(skip)]
+    samples: Vec<(f32, f32)>,
+}
+
+impl Recording {
+    /// reads the samples, lines which don't start with a number like headers and comments are skipped
+    pub fn parse(text: &str) -> Result<Vec<(f32, f32)>, String> {
+        let mut samples: Vec<(f32, f32)> = vec![];
+
+        for line in text.lines() {
+            let mut columns = line
+                .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+                .filter(|column| !column.is_empty());
+
+            let time = match columns.next().map(str::parse::<f32>) {
+                Some(Ok(x)) => x,
+                _ => continue,
+            };
+            let acceleration = match columns.next().map(str::parse::<f32>) {
+                Some(Ok(x)) => x,
+                _ => return Err(format!("no acceleration in line: {}", line)),
+            };
+
+            if samples.last().is_some_and(|(last, _)| *last >= time) {
+                return Err(format!("time has to go forward in line: {}", line));
+            }
+            samples.push((time, acceleration));
+        }
+
+        if samples.len() < 2 {
+            return Err("a recording needs at least two samples".to_string());
+        }
+        Ok(samples)
+    }
+
+    /// seconds from the first to the last sample
+    fn duration(&self) -> f32 {
+        self.samples[self.samples.len() - 1].0 - self.samples[0].0
+    }
+
+    /// the acceleration every step, linearly interpolated between the samples
+    fn resample(&self, step: f32) -> Vec<f32> {
+        let start = self.samples[0].0;
+        let steps = (self.duration() / step) as usize + 1;
+
+        let mut sample = 0;
+        (0..steps)
+            .map(|i| {
+                let time = start + i as f32 * step;
+                while sample + 2 < self.samples.len() && self.samples[sample + 1].0 < time {
+                    sample += 1;
+                }
+
+                let (time1, acceleration1) = self.samples[sample];
+                let (time2, acceleration2) = self.samples[sample + 1];
+                let fraction = ((time - time1) / (time2 - time1)).clamp(0.0, 1.0);
+                acceleration1 + (acceleration2 - acceleration1) * fraction
+            })
+            .collect()
+    }
+}
+
+/// The recording of the running quake resampled to the fixed step, replayed step by step
+#[derive(Resource, Default)]
+struct RecordingReplay {
+    /// number of the quake the samples belong to
+    quake: i32,
+    /// acceleration for every fixed step
+    samples: Vec<f32>,
+    /// the next sample to replay
+    step: usize,
+}
+
+/// Waves spreading from the epicenter along the plates, with an up and down
+/// pressure (P) part and a slower sideways shear (S) part
+#[derive(Deserialize, Clone)]
+struct SeismicWave {
+    /// index of the plate above the epicenter, a random one if not set
+    #[serde(default)]
+    epicenter: Option<usize>,
+    /// plates per second the P wave travels
+    speed: f32,
+    /// length of a wave in plates
+    wavelength: f32,
+    /// part of the strength lost with every plate of distance
+    attenuation: f32,
+    /// strength of the up and down P wave
+    vertical: f32,
+    /// strength of the sideways S wave
+    horizontal: f32,
+}
+
+impl SeismicWave {
+    /// push on a plate at a distance from the epicenter, some time into the quake
+    fn push(&self, distance: f32, time: f32) -> Vec2 {
+        Vec2::new(
+            self.horizontal * self.displacement(distance, time, self.speed * S_WAVE_SPEED_FACTOR),
+            self.vertical * self.displacement(distance, time, self.speed),
+        )
+    }
+
+    /// a sine wave which only moves plates once it has arrived and fades with distance
+    fn displacement(&self, distance: f32, time: f32, speed: f32) -> f32 {
+        let arrival = distance / speed;
+        if time < arrival {
+            return 0.0;
+        }
+
+        let phase = (time - arrival) * speed / self.wavelength;
+        (1.0 - self.attenuation).powf(distance) * (phase * std::f32::consts::TAU).sin()
+    }
+}
+
+/// How hard the plates are pushed
+#[derive(Deserialize, Clone)]
+enum Intensity {
+    /// the same force for the whole quake
+    Constant(f32),
+    /// goes from the start to the end force over the quake
+    Ramp {
+        /// force at the beginning of the quake
+        start: f32,
+        /// force at the end of the quake
+        end: f32,
+    },
+    /// gets stronger with every quake, `force * log10(count + 10)^4`
+    Escalating {
+        /// force of the first quake
+        force: f32,
+    },
+}
+
+impl Intensity {
+    /// force for the given quake number, at a fraction of the way through the quake
+    fn force(&self, count: i32, fraction: f32) -> f32 {
+        match self {
+            Intensity::Constant(force) => *force,
+            Intensity::Ramp { start, end } => start + (end - start) * fraction,
+            Intensity::Escalating { force } => force * (count as f32 + 10.0).log10().powi(4),
+        }
+    }
+}
+
+/// Which plates are pushed every rumble
+#[derive(Deserialize, Clone)]
+enum PlateSelection {
+    /// this many random plates, new ones for every rumble
+    Random(usize),
+    /// the plates with these indices
+    Indices(Vec<usize>),
+    /// the plates from the first to the last index
+    Range(usize, usize),
+}
+
+impl PlateSelection {
+    /// true if the plate with the index is pushed, random plates are picked separately
+    fn contains(&self, index: usize) -> bool {
+        match self {
+            PlateSelection::Random(_) => false,
+            PlateSelection::Indices(indices) => indices.contains(&index),
+            PlateSelection::Range(first, last) => (*first..=*last).contains(&index),
+        }
+    }
+
+    /// an error if the selection picks no plates or plates which don't exist
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            PlateSelection::Random(_) => Ok(()),
+            PlateSelection::Range(first, last) if first > last => {
+                Err(format!("the plate range {first} to {last} goes backwards"))
+            }
+            PlateSelection::Indices(indices) if indices.iter().any(|i| *i >= PLATE_COUNT) => Err(
+                format!("a quake kicks plates which are not among the {PLATE_COUNT} plates"),
+            ),
+            PlateSelection::Range(_, last) if *last >= PLATE_COUNT => Err(format!(
+                "the plate range ends at {last}, past the {PLATE_COUNT} plates"
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Which way the plates are pushed, they can only move up and down
+#[derive(Deserialize, Clone)]
+enum QuakeDirection {
+    /// plates are pushed up
+    Up,
+    /// plates are pulled down
+    Down,
+    /// up and down taking turns every rumble
+    Alternating,
+}
+
+/// Reads earthquake schedules from RON
+#[derive(Default)]
+struct EarthquakeScheduleLoader;
+
+impl AssetLoader for EarthquakeScheduleLoader {
+    type Asset = EarthquakeSchedule;
+    type Settings = ();
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+
+        let mut schedule: EarthquakeSchedule = ron::de::from_bytes(&bytes)?;
+        schedule.validate()?;
+
+        // recordings are read right away, they decide how long their quake is
+        let folder = load_context
+            .path()
+            .parent()
+            .unwrap_or(Path::new(""))
+            .to_owned();
+        for quake in schedule.quakes.iter_mut() {
+            if let Motion::Recording(recording) = &mut quake.motion {
+                let bytes = load_context
+                    .read_asset_bytes(folder.join(&recording.file))
+                    .await?;
+                recording.samples = Recording::parse(&String::from_utf8(bytes)?)?;
+                quake.duration = recording.duration();
+            }
+        }
+
+        Ok(schedule)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["quakes.ron"]
+    }
+}
+
+/// Difficulty profile from `assets/quakes`, can be set with the `QUAKE_PROFILE` environment variable
+fn quake_profile() -> String {
+    std::env::var("QUAKE_PROFILE").unwrap_or_else(|_| "normal".to_string())
+}
+
+/// How far the soil below the plates reaches down, deep enough for the deepest basement
+const SOIL_DEPTH: f32 = 200.0;
+/// Color of the soil below the plates
+const SOIL_COLOR: Color = Color::srgb(0.35, 0.25, 0.18);
 
 /// label which shows next cycle
 #[derive(Component)]
@@ -51,20 +425,35 @@ fn add_default_plates(mut cmd: Commands, asset_server: Res<AssetServer>) {
         ))
         .id();
 
+    // Soil below the plates, basements are dug into it
+    cmd.spawn(SpriteBundle {
+        sprite: Sprite {
+            color: SOIL_COLOR,
+            custom_size: Some(Vec2::new(2000.0, SOIL_DEPTH)),
+            ..default()
+        },
+        transform: Transform::from_xyz(0.0, -30.0 - height - SOIL_DEPTH / 2.0, -0.5),
+        ..default()
+    });
+
     // TODO: add joints
     let mut previous_plate: Option<Entity> = None;
 
     let mut x_offset: f32 = 0.0;
-    for i in 0..20 {
+    for i in 0..PLATE_COUNT {
         // Plates
 
         x_offset = -500.0 + i as f32 * width;
         let plate = cmd
             .spawn((
-                Plate,
+                Plate {
+                    index: i,
+                    home: Vec2::new(x_offset, -30.0 - height / 2.0),
+                },
                 RigidBody::Dynamic,
                 Collider::rectangle(width, height),
-                LockedAxes::ALL_LOCKED.unlock_translation_y(),
+                // sideways for shear waves
+                LockedAxes::ROTATION_LOCKED,
                 plates_layers(),
                 SpriteBundle {
                     texture: asset_server.load("ground.png"),
@@ -79,7 +468,10 @@ fn add_default_plates(mut cmd: Commands, asset_server: Res<AssetServer>) {
             .id();
 
         if let Some(previous_plate) = previous_plate {
-            cmd.spawn(DistanceJoint::new(previous_plate, plate).with_limits(width, height));
+            cmd.spawn(
+                DistanceJoint::new(previous_plate, plate)
+                    .with_limits(width - PLATE_SHEAR_SLACK, height + PLATE_SHEAR_SLACK),
+            );
         } else {
             cmd.spawn(
                 DistanceJoint::new(ground, plate)
@@ -140,7 +532,7 @@ fn add_default_plates(mut cmd: Commands, asset_server: Res<AssetServer>) {
 
 /// Timer for earthquake
 #[derive(Resource)]
-struct EarthquakeTimer {
+pub struct EarthquakeTimer {
     /// counter
     count: i32,
     /// the timer which decides when the next earthquake happens
@@ -149,49 +541,165 @@ struct EarthquakeTimer {
     stop: Timer,
     /// The timer inbetween "rumbles", aka the small earthquakes
     rumbles: Timer,
+    /// where the quakes come from, nothing happens until it is loaded
+    schedule: Handle<EarthquakeSchedule>,
+    /// the quake which is shaking right now, or shook last
+    quake: Option<Quake>,
+    /// index of the plate above the epicenter of the current quake
+    epicenter: usize,
+    /// the strongest push on a single plate during the current quake
+    peak_force: f32,
+    /// the largest average distance of the plates from where they rest during the current quake
+    peak_displacement: f32,
+    /// seeded, so the same seed kicks the same plates and picks the same epicenters
+    rng: StdRng,
 }
 
-fn init_timers(mut timers: ResMut<EarthquakeTimer>) {
-    timers.next.reset();
-    timers.stop.reset();
-    timers.rumbles.reset();
+impl EarthquakeTimer {
+    /// waits for the schedule, the countdown starts once it is loaded
+    pub fn new(seed: u64, schedule: Handle<EarthquakeSchedule>) -> Self {
+        let paused = |seconds: f32| {
+            let mut timer = Timer::from_seconds(seconds, TimerMode::Repeating);
+            timer.pause();
+            timer
+        };
 
-    timers.stop.pause();
-    timers.rumbles.pause();
+        EarthquakeTimer {
+            count: 0,
+            // durations come from the schedule
+            next: paused(25.0),
+            stop: paused(3.0),
+            rumbles: paused(0.1),
+            schedule,
+            quake: None,
+            epicenter: 0,
+            peak_force: 0.0,
+            peak_displacement: 0.0,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// true while the ground is shaking
+    pub fn is_running(&self) -> bool {
+        !self.stop.paused()
+    }
+
+    /// how many quakes started so far
+    pub fn count(&self) -> i32 {
+        self.count
+    }
+
+    /// the strongest push on a single plate during the current or last quake
+    pub fn peak_force(&self) -> f32 {
+        self.peak_force
+    }
+
+    /// the largest average distance of the plates from where they rest during the current or last quake
+    pub fn peak_displacement(&self) -> f32 {
+        self.peak_displacement
+    }
+}
+
+/// Average distance of the plates from where they rest
+pub fn average_displacement<'a>(
+    plates: impl ExactSizeIterator<Item = (&'a Plate, &'a Transform)>,
+) -> f32 {
+    let count = plates.len().max(1) as f32;
+    plates
+        .map(|(plate, transform)| transform.translation.xy().distance(plate.home))
+        .sum::<f32>()
+        / count
 }
 
-/// Generates the earthquace by forcing ceratin plates upwards
+/// Seed of the quakes, can be set with the `QUAKE_SEED` environment variable
+fn quake_seed() -> u64 {
+    std::env::var("QUAKE_SEED")
+        .ok()
+        .and_then(|seed| seed.parse().ok())
+        .unwrap_or_else(|| rand::thread_rng().gen())
+}
+
+/// Loads the schedule of the selected difficulty, the timers wait for it
+fn init_timers(mut cmd: Commands, asset_server: Res<AssetServer>) {
+    cmd.insert_resource(EarthquakeTimer::new(
+        quake_seed(),
+        asset_server.load(format!("quakes/{}.quakes.ron", quake_profile())),
+    ));
+}
+
+/// Generates the earthquake by kicking plates or sending waves through them
 fn earthquake(
     mut cmd: Commands,
-    plates: Query<Entity>,
+    plates: Query<(Entity, &Plate)>,
     delta: Res<Time>,
     mut timers: ResMut<EarthquakeTimer>,
     keys: Res<ButtonInput<KeyCode>>,
     mut sounds: Query<&mut AudioSink, With<EarthquakeSound>>,
+    schedules: Res<Assets<EarthquakeSchedule>>,
 ) {
-    timers.next.tick(delta.delta());
+    let schedule = match schedules.get(&timers.schedule) {
+        Some(x) => x,
+        None => return,
+    };
 
-    if sounds.is_empty() {
-        return;
+    // the countdown to the first quake starts once the schedule is there
+    if timers.next.paused() {
+        let first = schedule.quake(0);
+        timers
+            .next
+            .set_duration(Duration::from_secs_f32(first.delay));
+        timers.next.reset();
+        timers.next.unpause();
     }
-    let sound = sounds.single_mut();
+
+    timers.next.tick(delta.delta());
+
+    // the ground shakes the same without sound
+    let sound = sounds.get_single_mut().ok();
 
     let elapsed = timers.next.elapsed_secs();
     let remaining = timers.next.remaining_secs();
-    if remaining < 2.0 {
-        sound.play();
-        sound.set_volume(1.0 / 2.0 * (2.0 - remaining));
-    } else if timers.stop.paused() && elapsed <= 5.0 {
-        // an earthquake takes 3s
-        sound.set_volume(1.0 / 2.0 * (5.0 - elapsed));
+    let fade_out = timers.stop.duration().as_secs_f32() + 2.0;
+    if let Some(sound) = &sound {
+        if remaining < 2.0 {
+            sound.play();
+            sound.set_volume(1.0 / 2.0 * (2.0 - remaining));
+        } else if timers.stop.paused() && elapsed <= fade_out {
+            // fades out for 2s after the earthquake
+            sound.set_volume(1.0 / 2.0 * (fade_out - elapsed));
+        }
     }
 
     // if keys.just_pressed(KeyCode::KeyX)  {
     if timers.next.just_finished() {
-        sound.set_volume(1.0);
+        if let Some(sound) = &sound {
+            sound.set_volume(1.0);
+        }
+
+        let quake = schedule.quake(timers.count as usize);
         timers.count += 1;
-        let secs = (timers.next.duration().as_secs() - 1).max(5);
-        timers.next.set_duration(Duration::from_secs(secs));
+
+        let next = schedule.quake(timers.count as usize);
+        timers
+            .next
+            .set_duration(Duration::from_secs_f32(next.delay));
+        timers
+            .stop
+            .set_duration(Duration::from_secs_f32(quake.duration));
+        timers
+            .rumbles
+            .set_duration(Duration::from_secs_f32(quake.rumble));
+        timers.epicenter = match &quake.motion {
+            Motion::Wave(SeismicWave {
+                epicenter: Some(epicenter),
+                ..
+            }) => *epicenter,
+            _ => timers.rng.gen_range(0..plates.iter().len()),
+        };
+        timers.quake = Some(quake);
+        timers.peak_force = 0.0;
+        timers.peak_displacement = 0.0;
+
         timers.stop.unpause();
         timers.stop.reset();
         timers.rumbles.unpause();
@@ -206,21 +714,172 @@ fn earthquake(
         timers.rumbles.pause();
     }
 
+    // waves push every step, pushing only once per rumble would skip over short waves
+    let wave_peak = match &timers.quake {
+        Some(Quake {
+            motion: Motion::Wave(wave),
+            rumble,
+            intensity,
+            ..
+        }) if timers.is_running() => {
+            let force = intensity.force(timers.count, timers.stop.fraction());
+            let time = timers.stop.elapsed_secs();
+            let share = delta.delta_seconds() / rumble;
+
+            let mut peak_force = timers.peak_force;
+            for (entity, plate) in &plates {
+                let distance = plate.index.abs_diff(timers.epicenter) as f32;
+                let push = wave.push(distance, time) * force;
+                peak_force = peak_force.max(push.length());
+                cmd.entity(entity)
+                    .insert(ExternalForce::new(push * share).with_persistence(false));
+            }
+            Some(peak_force)
+        }
+        _ => None,
+    };
+    if let Some(peak_force) = wave_peak {
+        timers.peak_force = peak_force;
+        return;
+    }
+
+    let timers = &mut *timers;
     if timers.rumbles.just_finished() {
-        let mut rng = rand::thread_rng();
-        let earthquake_plates = plates.iter().choose_multiple(&mut rng, 6);
+        let quake = match &timers.quake {
+            Some(x) => x,
+            None => return,
+        };
+
+        let force = quake.intensity.force(timers.count, timers.stop.fraction());
+
+        let (selection, direction) = match &quake.motion {
+            Motion::Kicks { plates, direction } => (plates, direction),
+            // both move the plates every fixed step instead
+            Motion::Wave(_) | Motion::Recording(_) => return,
+        };
+
+        let earthquake_plates = match selection {
+            PlateSelection::Random(count) => plates
+                .iter()
+                .map(|(entity, _)| entity)
+                .choose_multiple(&mut timers.rng, *count),
+            _ => plates
+                .iter()
+                .filter(|(_, plate)| selection.contains(plate.index))
+                .map(|(entity, _)| entity)
+                .collect(),
+        };
+
+        let direction = match direction {
+            QuakeDirection::Up => Vec2::Y,
+            QuakeDirection::Down => Vec2::NEG_Y,
+            QuakeDirection::Alternating => {
+                let rumble = (timers.stop.elapsed_secs() / quake.rumble) as i32;
+                if rumble % 2 == 0 {
+                    Vec2::Y
+                } else {
+                    Vec2::NEG_Y
+                }
+            }
+        };
+
+        if !earthquake_plates.is_empty() {
+            timers.peak_force = timers.peak_force.max(force);
+        }
 
         for plate_entity in &earthquake_plates {
-            cmd.entity(*plate_entity).insert(
-                ExternalForce::new(
-                    Vec2::Y * 3000000.0 * (timers.count as f32 + 10.0).log10().powi(4),
-                )
-                .with_persistence(false),
-            );
+            cmd.entity(*plate_entity)
+                .insert(ExternalForce::new(direction * force).with_persistence(false));
         }
     }
 }
 
+/// Moves all plates with the recorded ground acceleration, one sample every fixed step.
+/// The same recording always shakes the same way.
+fn replay_recording(
+    mut timers: ResMut<EarthquakeTimer>,
+    mut replay: ResMut<RecordingReplay>,
+    mut plates: Query<(&mut LinearVelocity, &Mass), With<Plate>>,
+    time: Res<Time<Fixed>>,
+) {
+    if !timers.is_running() {
+        return;
+    }
+
+    let recording = match &timers.quake {
+        Some(Quake {
+            motion: Motion::Recording(recording),
+            ..
+        }) => recording,
+        _ => return,
+    };
+
+    let step = time.timestep().as_secs_f32();
+    if replay.quake != timers.count {
+        replay.quake = timers.count;
+        replay.samples = recording.resample(step);
+        replay.step = 0;
+    }
+
+    let acceleration = match replay.samples.get(replay.step) {
+        Some(x) => *x * recording.scale,
+        None => return,
+    };
+    replay.step += 1;
+
+    let direction = if recording.horizontal {
+        Vec2::X
+    } else {
+        Vec2::Y
+    };
+
+    // the push it would take to move the plate like that
+    let mut peak_force = timers.peak_force;
+    for (mut velocity, mass) in plates.iter_mut() {
+        velocity.0 += direction * acceleration * step;
+        peak_force = peak_force.max(mass.0 * acceleration.abs());
+    }
+    timers.peak_force = peak_force;
+}
+
+/// Keeps the largest displacement of the plates while the ground shakes
+fn track_peak_displacement(
+    mut timers: ResMut<EarthquakeTimer>,
+    plates: Query<(&Plate, &Transform)>,
+) {
+    if !timers.is_running() || plates.is_empty() {
+        return;
+    }
+
+    let displacement = average_displacement(plates.iter());
+    timers.peak_displacement = timers.peak_displacement.max(displacement);
+}
+
+/// Pulls plates back to where they rest, like the rock around them would.
+/// While the ground shakes only sideways, so the kicks up and down keep their full strength,
+/// and not at all while a recording is replayed, so the plates follow it exactly.
+fn settle_plates(
+    mut plates: Query<(&Plate, &Transform, &mut LinearVelocity)>,
+    timers: Res<EarthquakeTimer>,
+    time: Res<Time>,
+) {
+    let axes = match &timers.quake {
+        Some(Quake {
+            motion: Motion::Recording(_),
+            ..
+        }) if timers.is_running() => return,
+        _ if timers.is_running() => Vec2::X,
+        _ => Vec2::ONE,
+    };
+
+    for (plate, transform, mut velocity) in plates.iter_mut() {
+        let offset = transform.translation.xy() - plate.home;
+        let acceleration = -PLATE_SETTLE_STIFFNESS * offset - PLATE_SETTLE_DAMPING * velocity.0;
+
+        velocity.0 += axes * acceleration * time.delta_seconds();
+    }
+}
+
 /// Debug outline of plates
 fn outline_plates(plates: Query<&GlobalTransform, With<Plate>>, mut gizmos: Gizmos) {
     for transform in &plates {
@@ -255,14 +914,120 @@ pub struct EarthquakePlugin;
 
 impl Plugin for EarthquakePlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, (add_default_plates, init_timers))
-            .insert_resource(EarthquakeTimer {
-                count: 0,
-                next: Timer::from_seconds(25.0, TimerMode::Repeating),
-                stop: Timer::from_seconds(3.0, TimerMode::Repeating),
-                rumbles: Timer::from_seconds(0.1, TimerMode::Repeating),
-            })
-            .add_systems(FixedUpdate, earthquake)
+        app.init_asset::<EarthquakeSchedule>()
+            .init_asset_loader::<EarthquakeScheduleLoader>()
+            .add_systems(Startup, (add_default_plates, init_timers))
+            // replaced by init_timers once the schedule is loading
+            .insert_resource(EarthquakeTimer::new(0, Handle::default()))
+            .init_resource::<RecordingReplay>()
+            .add_systems(
+                FixedUpdate,
+                (
+                    earthquake,
+                    replay_recording,
+                    settle_plates,
+                    track_peak_displacement,
+                )
+                    .chain(),
+            )
             .add_systems(Update, update_earthquake_text);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::building::tests::quake_app;
+
+    /// The benchmark schedule with the recordings read and the first quake right away
+    fn replay_schedule() -> EarthquakeSchedule {
+        let mut schedule: EarthquakeSchedule =
+            ron::de::from_str(include_str!("../assets/quakes/replay.quakes.ron")).unwrap();
+        schedule.quakes[0].delay = 1.0;
+        for quake in schedule.quakes.iter_mut() {
+            if let Motion::Recording(recording) = &mut quake.motion {
+                recording.samples =
+                    Recording::parse(include_str!("../assets/quakes/synthetic.accel.csv")).unwrap();
+                quake.duration = recording.duration();
+            }
+        }
+        schedule
+    }
+
+    /// Positions of all plates every fixed step while the first recording is replayed
+    fn replay_trace() -> Vec<Vec<Vec2>> {
+        let mut app = quake_app(0, replay_schedule());
+
+        let mut trace = vec![];
+        loop {
+            app.update();
+
+            let timers = app.world().resource::<EarthquakeTimer>();
+            if timers.count() > 1 || timers.count() == 1 && !timers.is_running() {
+                break;
+            }
+            if timers.is_running() {
+                let mut plates = app.world_mut().query::<(&Plate, &Position)>();
+                let mut positions: Vec<(usize, Vec2)> = plates
+                    .iter(app.world())
+                    .map(|(plate, position)| (plate.index, position.0))
+                    .collect();
+                positions.sort_by_key(|(index, _)| *index);
+                trace.push(
+                    positions
+                        .into_iter()
+                        .map(|(_, position)| position)
+                        .collect(),
+                );
+            }
+        }
+        trace
+    }
+
+    /// A schedule with a single quake, the motion and timing in RON
+    fn single_quake(delay: f32, motion: &str) -> EarthquakeSchedule {
+        ron::de::from_str(&format!(
+            "(quakes: [(delay: {delay}, duration: 4.0, rumble: 0.1, intensity: Constant(1.0), \
+             motion: {motion})], speedup: 0.0, min_delay: 10.0)"
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn schedules_are_validated() {
+        for shipped in [
+            include_str!("../assets/quakes/easy.quakes.ron"),
+            include_str!("../assets/quakes/normal.quakes.ron"),
+            include_str!("../assets/quakes/hard.quakes.ron"),
+            include_str!("../assets/quakes/replay.quakes.ron"),
+        ] {
+            let schedule: EarthquakeSchedule = ron::de::from_str(shipped).unwrap();
+            assert!(schedule.validate().is_ok());
+        }
+
+        let kicks = "Kicks(plates: Range(2, 5), direction: Up)";
+        assert!(single_quake(5.0, kicks).validate().is_ok());
+
+        assert!(single_quake(-5.0, kicks).validate().is_err());
+        for plates in ["Range(5, 2)", "Range(2, 20)", "Indices([1, 20])"] {
+            let kicks = format!("Kicks(plates: {plates}, direction: Up)");
+            assert!(single_quake(5.0, &kicks).validate().is_err(), "{plates}");
+        }
+        let wave = "Wave((epicenter: Some(20), speed: 14.0, wavelength: 10.0, \
+                    attenuation: 0.05, vertical: 1.0, horizontal: 0.0))";
+        assert!(single_quake(5.0, wave).validate().is_err());
+    }
+
+    #[test]
+    fn replays_are_identical() {
+        let first = replay_trace();
+        let second = replay_trace();
+
+        assert!(first.len() > 100, "the recording should be replayed");
+        assert!(
+            first.iter().any(|positions| positions != &first[0]),
+            "the recording should move the plates"
+        );
+        assert!(first == second, "two replays moved the plates differently");
+    }
+}
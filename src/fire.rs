@@ -0,0 +1,404 @@
+//! Chimneys heating the tower, their smoke and the fires breaking out in the tower
+
+use avian2d::prelude::*;
+use bevy::{
+    color::palettes::css::{GRAY, ORANGE, RED, YELLOW},
+    prelude::*,
+};
+use rand::Rng;
+
+use crate::{
+    building::{
+        Building, BuildingDamaged, BuildingJoint, BuildingVariants, Chimney, UnderConstruction,
+        BUILDING_HEALTH,
+    },
+    earthquake::EarthquakeTimer,
+};
+
+/// Chimneys heat buildings up to this distance, the closer the warmer
+const HEATING_RADIUS: f32 = 200.0;
+
+/// How warm a building is kept by the chimneys around it, inhabitants like it warm
+#[derive(Component, Default)]
+pub struct Heated {
+    /// 0 is cold, 1 is fully heated
+    pub warmth: f32,
+}
+
+/// A puff of smoke rising from a chimney
+#[derive(Component)]
+struct Smoke {
+    /// direction and speed it drifts in
+    vel: Vec2,
+    /// despawns when finished, the smoke grows and fades meanwhile
+    lifetime: Timer,
+}
+
+/// Time between two puffs of smoke of every chimney
+#[derive(Resource)]
+struct SmokeTimer(Timer);
+
+/// Chimney buildings tilted further than this during an earthquake can start a fire
+const FIRE_TILT: f32 = 0.4;
+
+/// Chance per second a tilted chimney building starts burning
+const FIRE_CHANCE: f32 = 0.3;
+
+/// Chance a building without any health left catches fire after an earthquake,
+/// less damaged buildings are less likely to burn
+const DAMAGE_FIRE_CHANCE: f64 = 0.5;
+
+/// Extra chance a building which is still tilted catches fire after an earthquake
+const TILT_FIRE_CHANCE: f64 = 0.2;
+
+/// Fire stations fight fires up to this distance
+const FIRE_STATION_RADIUS: f32 = 300.0;
+
+/// Intensity per second a fire station takes away from every fire in reach
+const FIRE_STATION_POWER: f32 = 0.3;
+
+/// Health lost per second of a building burning at full intensity
+const BURN_DAMAGE: f32 = 6.0;
+
+/// Seconds a fire burns until it goes out on its own
+const FIRE_SECONDS: f32 = 20.0;
+
+/// Seconds between two attempts of a fire to spread
+const FIRE_SPREAD_SECONDS: f32 = 3.0;
+
+/// Chance a fire spreads to a touching or connected building on every attempt
+const FIRE_SPREAD_CHANCE: f64 = 0.4;
+
+/// A burning building, it hurts the inhabitants and makes them unhappy
+#[derive(Component)]
+pub struct OnFire {
+    /// how strong the fire burns, grows over time
+    pub intensity: f32,
+    /// the fire goes out when finished
+    lifetime: Timer,
+    /// when the fire tries to spread
+    spread: Timer,
+}
+
+impl OnFire {
+    /// a fresh small fire
+    fn new() -> Self {
+        OnFire {
+            intensity: 0.2,
+            lifetime: Timer::from_seconds(FIRE_SECONDS, TimerMode::Once),
+            spread: Timer::from_seconds(FIRE_SPREAD_SECONDS, TimerMode::Repeating),
+        }
+    }
+}
+
+/// Computes how warm every building is from the chimneys around it
+fn heat_buildings(
+    mut cmd: Commands,
+    mut buildings: Query<(Entity, &GlobalTransform, Option<&mut Heated>), With<Building>>,
+    chimneys: Query<(&Parent, &GlobalTransform), With<Chimney>>,
+) {
+    for (entity, transform, heated) in buildings.iter_mut() {
+        let warmth: f32 = chimneys
+            .iter()
+            .map(|(parent, chimney_transform)| {
+                if parent.get() == entity {
+                    return 1.0;
+                }
+
+                let distance = chimney_transform
+                    .translation()
+                    .xy()
+                    .distance(transform.translation().xy());
+                (1.0 - distance / HEATING_RADIUS).max(0.0)
+            })
+            .sum();
+        let warmth = warmth.min(1.0);
+
+        match heated {
+            Some(mut heated) => heated.warmth = warmth,
+            None => {
+                cmd.entity(entity).insert(Heated { warmth });
+            }
+        }
+    }
+}
+
+/// Every chimney puffs smoke every now and then
+fn emit_smoke(
+    mut cmd: Commands,
+    time: Res<Time>,
+    mut timer: ResMut<SmokeTimer>,
+    chimneys: Query<&GlobalTransform, With<Chimney>>,
+) {
+    timer.0.tick(time.delta());
+
+    if !timer.0.just_finished() {
+        return;
+    }
+
+    let mut rng = rand::thread_rng();
+
+    for transform in &chimneys {
+        cmd.spawn((
+            Smoke {
+                vel: Vec2::new(rng.gen_range(-10.0..10.0), rng.gen_range(20.0..40.0)),
+                lifetime: Timer::from_seconds(3.0, TimerMode::Once),
+            },
+            TransformBundle::from_transform(Transform::from_translation(
+                transform.translation() + transform.up() * 15.0,
+            )),
+        ));
+    }
+}
+
+/// Moves the smoke upwards and removes it once it faded
+fn update_smoke(
+    mut cmd: Commands,
+    time: Res<Time>,
+    mut smokes: Query<(Entity, &mut Smoke, &mut Transform)>,
+) {
+    for (entity, mut smoke, mut transform) in smokes.iter_mut() {
+        smoke.lifetime.tick(time.delta());
+
+        if smoke.lifetime.finished() {
+            cmd.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        transform.translation += (smoke.vel * time.delta_seconds()).extend(0.0);
+    }
+}
+
+/// Draws the smoke as growing, fading circles
+fn display_smoke(smokes: Query<(&Smoke, &GlobalTransform)>, mut gizmos: Gizmos) {
+    for (smoke, transform) in &smokes {
+        let fraction = smoke.lifetime.fraction();
+
+        gizmos.circle_2d(
+            transform.translation().xy(),
+            5.0 + 15.0 * fraction,
+            Color::from(GRAY).with_alpha(1.0 - fraction),
+        );
+    }
+}
+
+/// Chimney buildings which tilt too much during an earthquake can catch fire
+fn ignite_tilted_chimneys(
+    mut cmd: Commands,
+    time: Res<Time>,
+    earthquake: Res<EarthquakeTimer>,
+    chimneys: Query<&Parent, With<Chimney>>,
+    buildings: Query<&GlobalTransform, (With<Building>, Without<OnFire>)>,
+) {
+    if !earthquake.is_running() {
+        return;
+    }
+
+    let mut rng = rand::thread_rng();
+
+    for parent in &chimneys {
+        let transform = match buildings.get(parent.get()) {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+
+        let tilt = transform.right().xy().to_angle().abs();
+        if tilt > FIRE_TILT && rng.gen::<f32>() < FIRE_CHANCE * time.delta_seconds() {
+            cmd.entity(parent.get()).insert(OnFire::new());
+        }
+    }
+}
+
+/// Damaged buildings can catch fire once an earthquake is over, the more damaged the likelier
+fn ignite_damaged_buildings(
+    mut cmd: Commands,
+    earthquake: Res<EarthquakeTimer>,
+    mut was_running: Local<bool>,
+    buildings: Query<(Entity, &Building, &GlobalTransform), Without<OnFire>>,
+) {
+    let ended = *was_running && !earthquake.is_running();
+    *was_running = earthquake.is_running();
+
+    if !ended {
+        return;
+    }
+
+    let mut rng = rand::thread_rng();
+
+    for (entity, building, transform) in &buildings {
+        let damage = (1.0 - building.health / BUILDING_HEALTH).clamp(0.0, 1.0) as f64;
+        let tilted = transform.right().xy().to_angle().abs() > FIRE_TILT;
+
+        let mut chance = DAMAGE_FIRE_CHANCE * damage;
+        if tilted {
+            chance += TILT_FIRE_CHANCE;
+        }
+
+        if rng.gen_bool(chance.min(1.0)) {
+            cmd.entity(entity).insert(OnFire::new());
+        }
+    }
+}
+
+/// Fires damage their building, grow, spread to touching or connected buildings
+/// and eventually go out
+fn burn_buildings(
+    mut cmd: Commands,
+    time: Res<Time>,
+    collisions: Res<Collisions>,
+    joints: Query<&BuildingJoint>,
+    mut burning: Query<(Entity, &mut OnFire, &mut Building)>,
+    buildings: Query<Has<OnFire>, With<Building>>,
+    mut damaged: EventWriter<BuildingDamaged>,
+) {
+    let mut rng = rand::thread_rng();
+
+    for (entity, mut fire, mut building) in burning.iter_mut() {
+        fire.lifetime.tick(time.delta());
+        fire.spread.tick(time.delta());
+
+        if fire.lifetime.finished() {
+            cmd.entity(entity).remove::<OnFire>();
+            continue;
+        }
+
+        fire.intensity = (fire.intensity + 0.1 * time.delta_seconds()).min(1.0);
+
+        let damage = BURN_DAMAGE * fire.intensity * time.delta_seconds();
+        building.health -= damage;
+        damaged.send(BuildingDamaged {
+            building: entity,
+            damage,
+        });
+
+        if !fire.spread.just_finished() {
+            continue;
+        }
+
+        let touching = collisions.collisions_with_entity(entity).map(|contacts| {
+            if contacts.entity1 == entity {
+                contacts.entity2
+            } else {
+                contacts.entity1
+            }
+        });
+        let connected = joints.iter().filter_map(|joint| {
+            if joint.entity1 == entity {
+                Some(joint.entity2)
+            } else if joint.entity2 == entity {
+                Some(joint.entity1)
+            } else {
+                None
+            }
+        });
+
+        for other in touching.chain(connected) {
+            // inserting a new fire on a burning building would restart it
+            if buildings.get(other) != Ok(false) || !rng.gen_bool(FIRE_SPREAD_CHANCE) {
+                continue;
+            }
+
+            cmd.entity(other).try_insert(OnFire::new());
+        }
+    }
+}
+
+/// Finished fire stations weaken all fires around them until they go out
+fn fight_fires(
+    mut cmd: Commands,
+    time: Res<Time>,
+    stations: Query<(&Building, &GlobalTransform), Without<UnderConstruction>>,
+    mut burning: Query<(Entity, &mut OnFire, &GlobalTransform)>,
+) {
+    for (entity, mut fire, transform) in burning.iter_mut() {
+        let in_reach = stations
+            .iter()
+            .filter(|(station, station_transform)| {
+                station.variant == BuildingVariants::FireStation
+                    && station_transform
+                        .translation()
+                        .distance(transform.translation())
+                        < FIRE_STATION_RADIUS
+            })
+            .count();
+
+        fire.intensity -= in_reach as f32 * FIRE_STATION_POWER * time.delta_seconds();
+
+        if fire.intensity <= 0.0 {
+            cmd.entity(entity).remove::<OnFire>();
+        }
+    }
+}
+
+/// Draws a siren on the roof of fire stations
+fn display_fire_stations(
+    stations: Query<(&Building, &GlobalTransform)>,
+    time: Res<Time>,
+    mut gizmos: Gizmos,
+) {
+    // blinks once a second
+    let color = if time.elapsed_seconds().fract() < 0.5 {
+        RED
+    } else {
+        ORANGE
+    };
+
+    for (station, transform) in &stations {
+        if station.variant != BuildingVariants::FireStation {
+            continue;
+        }
+
+        let roof =
+            transform.translation().xy() + transform.up().xy() * (station.size.y / 2.0 + 6.0);
+        gizmos.circle_2d(roof, 6.0, color);
+    }
+}
+
+/// Draws flickering flames on the roof of burning buildings
+fn display_fire(burning: Query<(&OnFire, &Building, &GlobalTransform)>, mut gizmos: Gizmos) {
+    let mut rng = rand::thread_rng();
+
+    for (fire, building, transform) in &burning {
+        let roof = transform.translation().xy() + transform.up().xy() * building.size.y / 2.0;
+        let flames = 2 + (fire.intensity * 4.0) as usize;
+
+        for i in 0..flames {
+            let x = (i as f32 / (flames - 1) as f32 - 0.5) * 0.8 * building.size.x;
+            let base = roof + transform.right().xy() * x;
+            let height = (15.0 + 25.0 * fire.intensity) * rng.gen_range(0.7..1.0);
+
+            gizmos.linestrip_2d(
+                [
+                    base - transform.right().xy() * 6.0,
+                    base + transform.up().xy() * height,
+                    base + transform.right().xy() * 6.0,
+                ],
+                if i % 2 == 0 { ORANGE } else { RED },
+            );
+            gizmos.line_2d(base, base + transform.up().xy() * height * 0.5, YELLOW);
+        }
+    }
+}
+
+/// Chimneys, smoke, fires and firefighting bundled into a plugin
+pub struct FirePlugin;
+
+impl Plugin for FirePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SmokeTimer(Timer::from_seconds(0.5, TimerMode::Repeating)))
+            .add_systems(
+                Update,
+                (
+                    heat_buildings,
+                    emit_smoke,
+                    update_smoke,
+                    display_smoke,
+                    ignite_tilted_chimneys,
+                    ignite_damaged_buildings,
+                    (fight_fires, burn_buildings).chain(),
+                    display_fire,
+                    display_fire_stations,
+                ),
+            );
+    }
+}
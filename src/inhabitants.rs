@@ -10,7 +10,15 @@ use bevy::{
 use rand::seq::SliceRandom;
 use rand::Rng;
 
-use crate::{building::Building, player::Player};
+use crate::{
+    building::{
+        Building, BuildingDamaged, BuildingJoint, BuildingVariants, UnderConstruction,
+        GARDEN_RADIUS, GARDEN_RENT_BONUS,
+    },
+    earthquake::EarthquakeTimer,
+    fire::{Heated, OnFire},
+    player::Player,
+};
 
 /// Width of a inhabitant
 const WIDTH: f32 = 30.0;
@@ -19,11 +27,37 @@ const HEIGHT: f32 = 40.0;
 
 /// An inhabitant with its home building marked
 #[derive(Component)]
-struct Inhabitant {
+pub struct Inhabitant {
     /// next point inhabitant is walking to
     target_x: f32,
     /// when to move again
     move_timer: Timer,
+    /// goes down when the building gets damaged, dies at zero
+    health: f32,
+    /// happy inhabitants pay more rent, 1 is neutral
+    happiness: f32,
+}
+
+/// Extra happiness of inhabitants in a fully heated building
+const HEATING_HAPPINESS: f32 = 0.3;
+
+/// Happiness lost by inhabitants of a building burning at full intensity
+const FIRE_UNHAPPINESS: f32 = 0.8;
+
+/// Health of a new inhabitant
+const INHABITANT_HEALTH: f32 = 100.0;
+
+/// Part of the damage of a building the inhabitants inside take
+const INHABITANT_DAMAGE_FACTOR: f32 = 0.8;
+
+/// Inhabitants of a damaged building reach shelters this close, or joined to their building
+const SHELTER_RADIUS: f32 = 250.0;
+
+/// An inhabitant hiding in a shelter, it goes back to its home once the quake is over
+#[derive(Component)]
+struct Sheltered {
+    /// the building the inhabitant lives in
+    home: Entity,
 }
 
 /// when the inhabitant will talk
@@ -39,6 +73,9 @@ struct MoneyVisual {
     death: Timer,
 }
 
+/// Seconds between two rent payments of an inhabitant
+pub const RENT_SECONDS: f32 = 10.0;
+
 /// The timer when the inhabitant pays rent
 #[derive(Component)]
 struct RentTimer(Timer);
@@ -47,6 +84,10 @@ struct RentTimer(Timer);
 #[derive(Event)]
 pub struct SpawnNewInhabitant(pub Entity);
 
+/// An inhabitant died, moving out or being evicted does not count
+#[derive(Event)]
+pub struct InhabitantDied;
+
 /// moves inhabitants randomly but if the building is rotated, they start falling down
 fn move_inside_building(
     time: Res<Time>,
@@ -105,8 +146,10 @@ fn handle_spawn_new_inhabitant(
                 Inhabitant {
                     target_x: 0.0,
                     move_timer: Timer::from_seconds(0.0, TimerMode::Repeating),
+                    health: INHABITANT_HEALTH,
+                    happiness: 1.0,
                 },
-                RentTimer(Timer::from_seconds(10.0, TimerMode::Repeating)),
+                RentTimer(Timer::from_seconds(RENT_SECONDS, TimerMode::Repeating)),
                 TalkTimer(Timer::from_seconds(10.0, TimerMode::Repeating)),
                 SpriteBundle {
                     texture: asset_server.load(inhabs.choose(&mut rng).unwrap().to_string()),
@@ -123,38 +166,199 @@ fn handle_spawn_new_inhabitant(
 /// Should also die on hard impacts but i dont know how
 fn check_inhabitant_death(
     mut cmd: Commands,
-    inhabs: Query<(Entity, &GlobalTransform), With<Inhabitant>>,
+    mut inhabs: Query<(Entity, &mut Inhabitant, &GlobalTransform)>,
+    mut died: EventWriter<InhabitantDied>,
 ) {
-    for (entity, global) in inhabs.iter() {
+    for (entity, mut inhabitant, global) in inhabs.iter_mut() {
         let angle = global.right().xy().to_angle();
 
+        // already dead, the despawn is still pending
+        if inhabitant.health <= 0.0 {
+            continue;
+        }
+
         if angle.abs() > 0.9 * (PI / 2.0) {
+            inhabitant.health = 0.0;
             cmd.entity(entity).despawn_recursive();
+            died.send(InhabitantDied);
+        }
+    }
+}
+
+/// Inhabitants are happier in warm buildings, but not if it is burning
+fn update_happiness(
+    mut inhabitants: Query<(&mut Inhabitant, &Parent)>,
+    buildings: Query<(Option<&Heated>, Option<&OnFire>)>,
+) {
+    for (mut inhabitant, parent) in inhabitants.iter_mut() {
+        let (heated, fire) = match buildings.get(parent.get()) {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+
+        let warmth = heated.map_or(0.0, |heated| heated.warmth);
+        let fire = fire.map_or(0.0, |fire| fire.intensity);
+        inhabitant.happiness =
+            (1.0 + HEATING_HAPPINESS * warmth - FIRE_UNHAPPINESS * fire).max(0.0);
+    }
+}
+
+/// Inhabitants get hurt when their building is damaged, unless there is room for them in another
+/// shelter close by or joined to their building. Then they hide in that shelter until the quake is over.
+fn hurt_inhabitants(
+    mut cmd: Commands,
+    mut events: EventReader<BuildingDamaged>,
+    mut inhabitants: Query<(Entity, &mut Inhabitant, &Parent, Option<&Sheltered>)>,
+    shelters: Query<(Entity, &Building, &GlobalTransform), Without<UnderConstruction>>,
+    buildings: Query<&GlobalTransform, With<Building>>,
+    joints: Query<&BuildingJoint>,
+    mut died: EventWriter<InhabitantDied>,
+) {
+    // room left in every shelter
+    let mut rooms: Vec<(Entity, Vec2, usize)> = shelters
+        .iter()
+        .filter(|(_, building, _)| building.shelter_capacity() > 0)
+        .map(|(entity, building, transform)| {
+            let hiding = inhabitants
+                .iter()
+                .filter(|(_, _, parent, sheltered)| parent.get() == entity && sheltered.is_some())
+                .count();
+            (
+                entity,
+                transform.translation().xy(),
+                building.shelter_capacity().saturating_sub(hiding),
+            )
+        })
+        .collect();
+    let mut sheltered: Vec<Entity> = vec![];
+
+    for event in events.read() {
+        let position = match buildings.get(event.building) {
+            Ok(transform) => transform.translation().xy(),
+            Err(_) => continue,
+        };
+        let joined = |shelter: Entity| {
+            joints.iter().any(|joint| {
+                (joint.entity1 == event.building && joint.entity2 == shelter)
+                    || (joint.entity2 == event.building && joint.entity1 == shelter)
+            })
+        };
+
+        for (entity, mut inhabitant, parent, hiding) in inhabitants.iter_mut() {
+            if parent.get() != event.building
+                || inhabitant.health <= 0.0
+                || sheltered.contains(&entity)
+            {
+                continue;
+            }
+
+            let shelter = rooms
+                .iter_mut()
+                .filter(|(shelter, shelter_position, room)| {
+                    *room > 0
+                        && *shelter != event.building
+                        && (joined(*shelter)
+                            || shelter_position.distance(position) < SHELTER_RADIUS)
+                })
+                .min_by(|(_, a, _), (_, b, _)| {
+                    a.distance(position).total_cmp(&b.distance(position))
+                });
+
+            if let Some((shelter, _, room)) = shelter {
+                *room -= 1;
+                sheltered.push(entity);
+                let home = hiding.map_or(event.building, |hiding| hiding.home);
+                cmd.entity(*shelter).add_child(entity);
+                cmd.entity(entity).insert(Sheltered { home });
+                continue;
+            }
+
+            inhabitant.health -= event.damage * INHABITANT_DAMAGE_FACTOR;
+
+            if inhabitant.health <= 0.0 {
+                cmd.entity(entity).despawn_recursive();
+                died.send(InhabitantDied);
+            }
         }
     }
 }
 
+/// Sends hiding inhabitants back home once the quake is over, they stay if their home is gone
+fn leave_shelters(
+    mut cmd: Commands,
+    earthquake: Res<EarthquakeTimer>,
+    sheltered: Query<(Entity, &Sheltered)>,
+    buildings: Query<(), With<Building>>,
+) {
+    if earthquake.is_running() {
+        return;
+    }
+
+    for (entity, sheltered) in &sheltered {
+        if buildings.contains(sheltered.home) {
+            cmd.entity(sheltered.home).add_child(entity);
+        }
+        cmd.entity(entity).remove::<Sheltered>();
+    }
+}
+
+/// The rent an inhabitant pays every [`RENT_SECONDS`] for living in the given building
+pub fn rent(
+    inhabitant: &Inhabitant,
+    home: Entity,
+    buildings: &Query<(Entity, &Building, &GlobalTransform)>,
+) -> Option<f32> {
+    let (_, building, building_global) = match buildings.get(home) {
+        Ok(x) => x,
+        Err(_) => return None,
+    };
+
+    // neighbours enjoy the view of a garden roof
+    let close_to_garden = buildings
+        .iter()
+        .any(|(other, other_building, other_global)| {
+            other_building.variant == BuildingVariants::GardenRoof
+                && other != home
+                && other_global
+                    .translation()
+                    .distance(building_global.translation())
+                    < GARDEN_RADIUS
+        });
+    let garden_bonus = if close_to_garden {
+        GARDEN_RENT_BONUS
+    } else {
+        1.0
+    };
+
+    Some(
+        (building_global.translation().y * 0.5 + building.floor_space())
+            * building.material.rent_multiplier()
+            * building.variant.rent_multiplier()
+            * garden_bonus
+            * inhabitant.happiness,
+    )
+}
+
 /// ADds money to the player and spawn a money particle
 fn handle_rent_timers(
     mut cmd: Commands,
-    mut timers: Query<(&GlobalTransform, &mut RentTimer, &Parent)>,
-    buildings: Query<(&Building, &GlobalTransform)>,
+    mut timers: Query<(&GlobalTransform, &mut RentTimer, &Parent, &Inhabitant)>,
+    buildings: Query<(Entity, &Building, &GlobalTransform)>,
     time: Res<Time>,
     mut player: ResMut<Player>,
     assets: Res<AssetServer>,
 ) {
-    for (rent_global, mut rent_timer, parent) in timers.iter_mut() {
+    for (rent_global, mut rent_timer, parent, inhabitant) in timers.iter_mut() {
         let timer = &mut rent_timer.0;
         timer.tick(time.delta());
 
         if timer.just_finished() {
-            let (building, building_global) = match buildings.get(parent.get()) {
-                Ok(x) => x,
-                Err(_) => continue,
+            let rent = match rent(inhabitant, parent.get(), &buildings) {
+                Some(x) => x,
+                None => continue,
             };
 
             // should probably be an event
-            let rent = building_global.translation().y * 0.5 + building.size.x;
             player.money += rent as i64;
 
             cmd.spawn(AudioBundle {
@@ -283,11 +487,15 @@ pub struct InhabitantPlugin;
 impl Plugin for InhabitantPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<SpawnNewInhabitant>()
+            .add_event::<InhabitantDied>()
             .add_systems(
                 Update,
                 (
                     move_inside_building,
                     check_inhabitant_death,
+                    hurt_inhabitants,
+                    leave_shelters,
+                    update_happiness,
                     // outline_inhabitant,
                     update_money,
                     // draw_money,
@@ -299,3 +507,73 @@ impl Plugin for InhabitantPlugin {
             );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy::{asset::Handle, ecs::system::RunSystemOnce};
+
+    use super::*;
+    use crate::building::{BasementRoom, BuildingMaterial, BUILDING_HEALTH};
+
+    fn spawn_building(world: &mut World, x: f32, variant: BuildingVariants) -> Entity {
+        world
+            .spawn((
+                Building {
+                    size: Vec2::new(200.0, 100.0),
+                    cost: 0,
+                    material: BuildingMaterial::Brick,
+                    variant,
+                    health: BUILDING_HEALTH,
+                },
+                GlobalTransform::from_translation(Vec3::new(x, 0.0, 0.0)),
+            ))
+            .id()
+    }
+
+    /// Damages the building and lets its inhabitants take cover or get hurt
+    fn damage(world: &mut World, building: Entity) {
+        world.send_event(BuildingDamaged {
+            building,
+            damage: 10.0,
+        });
+        world.run_system_once(hurt_inhabitants);
+        world.resource_mut::<Events<BuildingDamaged>>().clear();
+    }
+
+    #[test]
+    fn sheltered_inhabitants_go_home_after_the_quake() {
+        let mut world = World::new();
+        world.init_resource::<Events<BuildingDamaged>>();
+        world.init_resource::<Events<InhabitantDied>>();
+        world.insert_resource(EarthquakeTimer::new(0, Handle::default()));
+
+        let home = spawn_building(&mut world, 0.0, BuildingVariants::Default);
+        let shelter = spawn_building(
+            &mut world,
+            100.0,
+            BuildingVariants::Basement(BasementRoom::Shelter),
+        );
+        let inhabitant = world
+            .spawn(Inhabitant {
+                target_x: 0.0,
+                move_timer: Timer::from_seconds(0.0, TimerMode::Repeating),
+                health: INHABITANT_HEALTH,
+                happiness: 1.0,
+            })
+            .set_parent(home)
+            .id();
+        let health = |world: &World| world.get::<Inhabitant>(inhabitant).unwrap().health;
+
+        damage(&mut world, home);
+        assert_eq!(world.get::<Parent>(inhabitant).unwrap().get(), shelter);
+        assert_eq!(health(&world), INHABITANT_HEALTH);
+
+        // there is nowhere else to hide
+        damage(&mut world, shelter);
+        assert!(health(&world) < INHABITANT_HEALTH);
+
+        world.run_system_once(leave_shelters);
+        assert_eq!(world.get::<Parent>(inhabitant).unwrap().get(), home);
+        assert!(world.get::<Sheltered>(inhabitant).is_none());
+    }
+}
@@ -17,6 +17,12 @@ pub enum Layers {
     Plates,
     /// The ground keeps the plates at a certain level
     Ground,
+    /// Pieces of collapsed buildings
+    Debris,
+    /// Base isolator pads between ground floors and plates
+    Isolator,
+    /// Soil at the bottom of the pits basements are dug into
+    Soil,
 }
 
 /// layers required by Building
@@ -28,6 +34,36 @@ pub fn building_layers() -> CollisionLayers {
             Layers::Cursor,
             Layers::Building,
             Layers::Plates,
+            Layers::Debris,
+            Layers::Isolator,
+        ],
+    )
+}
+
+/// layers required by a Building resting on an isolator pad, it no longer touches the plates
+pub fn isolated_building_layers() -> CollisionLayers {
+    CollisionLayers::new(
+        Layers::Building,
+        [
+            Layers::PreviewBuilding,
+            Layers::Cursor,
+            Layers::Building,
+            Layers::Debris,
+            Layers::Isolator,
+        ],
+    )
+}
+
+/// layers required by a basement, it is dug into the plates and the ground and rests on the soil
+pub fn basement_layers() -> CollisionLayers {
+    CollisionLayers::new(
+        Layers::Building,
+        [
+            Layers::PreviewBuilding,
+            Layers::Cursor,
+            Layers::Building,
+            Layers::Debris,
+            Layers::Soil,
         ],
     )
 }
@@ -46,16 +82,60 @@ pub fn cursor_builder_layers() -> CollisionLayers {
 pub fn preview_building_layers() -> CollisionLayers {
     CollisionLayers::new(
         Layers::PreviewBuilding,
-        [Layers::Building, Layers::Chimney, Layers::Plates],
+        [
+            Layers::Building,
+            Layers::Chimney,
+            Layers::Plates,
+            Layers::Isolator,
+        ],
     )
 }
 
 /// layers required by plates
 pub fn plates_layers() -> CollisionLayers {
-    CollisionLayers::new(Layers::Plates, [Layers::Building, Layers::Ground])
+    CollisionLayers::new(
+        Layers::Plates,
+        [
+            Layers::Building,
+            Layers::Ground,
+            Layers::Debris,
+            Layers::Isolator,
+        ],
+    )
 }
 
 /// layers required by ground
 pub fn ground_layers() -> CollisionLayers {
     CollisionLayers::new(Layers::Ground, [Layers::Plates])
 }
+
+/// layers required by the soil under a basement, only basements rest on it
+pub fn soil_layers() -> CollisionLayers {
+    CollisionLayers::new(Layers::Soil, [Layers::Building])
+}
+
+/// layers required by debris
+pub fn debris_layers() -> CollisionLayers {
+    CollisionLayers::new(
+        Layers::Debris,
+        [
+            Layers::Building,
+            Layers::Plates,
+            Layers::Debris,
+            Layers::Isolator,
+        ],
+    )
+}
+
+/// layers required by isolator pads, their building rests on them
+pub fn isolator_layers() -> CollisionLayers {
+    CollisionLayers::new(
+        Layers::Isolator,
+        [
+            Layers::PreviewBuilding,
+            Layers::Building,
+            Layers::Plates,
+            Layers::Debris,
+        ],
+    )
+}
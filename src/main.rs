@@ -7,9 +7,12 @@
 
 mod building;
 mod earthquake;
+mod fire;
 mod inhabitants;
 mod layers;
 mod player;
+mod report;
+mod seismograph;
 
 use avian2d::{debug_render::PhysicsDebugPlugin, PhysicsPlugins};
 use bevy::audio::Volume;
@@ -18,8 +21,11 @@ use bevy::{asset::AssetMetaCheck, window::WindowResolution};
 use bevy_screen_diagnostics::{
     ScreenDiagnosticsPlugin, ScreenEntityDiagnosticsPlugin, ScreenFrameDiagnosticsPlugin,
 };
+use fire::FirePlugin;
 use inhabitants::InhabitantPlugin;
 use player::PlayerPlugin;
+use report::QuakeReportPlugin;
+use seismograph::SeismographPlugin;
 
 use crate::{building::BuildingsPlugin, earthquake::EarthquakePlugin};
 
@@ -56,8 +62,11 @@ fn main() {
         .add_plugins((
             BuildingsPlugin,
             EarthquakePlugin,
+            FirePlugin,
             InhabitantPlugin,
             PlayerPlugin,
+            QuakeReportPlugin,
+            SeismographPlugin,
         ))
         .add_plugins(PhysicsPlugins::default())
         // .add_plugins(PhysicsDebugPlugin::default())
@@ -0,0 +1,279 @@
+//! Damage report after every earthquake and the history of all of them
+
+use bevy::{
+    color::palettes::css::{BLACK, WHITE},
+    prelude::*,
+    utils::HashMap,
+};
+
+use crate::{
+    building::{Building, BuildingCollapsed, JointBroken},
+    earthquake::EarthquakeTimer,
+    inhabitants::{rent, Inhabitant, InhabitantDied, RENT_SECONDS},
+    seismograph::magnitude,
+};
+
+/// Seconds the report stays on screen after a quake
+const REPORT_SECONDS: f32 = 10.0;
+
+/// What a single quake did to the city
+struct QuakeReport {
+    /// which quake it was
+    number: i32,
+    /// how strong it was, see [`magnitude`]
+    magnitude: f32,
+    /// buildings which collapsed
+    buildings_lost: usize,
+    /// joints which were torn apart
+    joints_snapped: usize,
+    /// inhabitants who died
+    inhabitants_killed: usize,
+    /// rent every [`RENT_SECONDS`] which the city pays less than before
+    rent_lost: f32,
+    /// the largest change of a building's rotation in degrees
+    peak_tilt: f32,
+}
+
+impl QuakeReport {
+    /// the report as it pops up after the quake
+    fn summary(&self) -> String {
+        format!(
+            "Earthquake #{} - Magnitude {:.1}\n\
+             Buildings lost: {}\n\
+             Joints snapped: {}\n\
+             Inhabitants killed: {}\n\
+             Rent lost: {}$ every {}s\n\
+             Peak tilt: {:.0}°\n\
+             (L for all quakes)",
+            self.number,
+            self.magnitude,
+            self.buildings_lost,
+            self.joints_snapped,
+            self.inhabitants_killed,
+            self.rent_lost as i64,
+            RENT_SECONDS,
+            self.peak_tilt,
+        )
+    }
+
+    /// the report squeezed into one line of the history
+    fn line(&self) -> String {
+        format!(
+            "#{} M {:.1}: {} lost, {} joints, {} dead, -{}$, {:.0}°",
+            self.number,
+            self.magnitude,
+            self.buildings_lost,
+            self.joints_snapped,
+            self.inhabitants_killed,
+            self.rent_lost as i64,
+            self.peak_tilt,
+        )
+    }
+}
+
+/// Counts the damage of the quake which is shaking right now
+#[derive(Resource, Default)]
+struct DamageTally {
+    /// true while a quake is counted
+    running: bool,
+    /// rent the city paid before the quake
+    rent_before: f32,
+    /// which way every building pointed before the quake, buildings built since are left out
+    angles_before: HashMap<Entity, Vec2>,
+    /// see [`QuakeReport`]
+    buildings_lost: usize,
+    /// see [`QuakeReport`]
+    joints_snapped: usize,
+    /// see [`QuakeReport`]
+    inhabitants_killed: usize,
+    /// see [`QuakeReport`]
+    peak_tilt: f32,
+}
+
+/// Every quake of the run, oldest first
+#[derive(Resource)]
+struct QuakeHistory {
+    /// the reports
+    reports: Vec<QuakeReport>,
+    /// how long the last report stays on screen
+    toast: Timer,
+    /// shows the whole history instead of the last report
+    open: bool,
+}
+
+/// Label showing the last report or the history
+#[derive(Component)]
+struct QuakeReportLabel;
+
+/// The rent all inhabitants pay together every [`RENT_SECONDS`]
+fn total_rent(
+    inhabitants: &Query<(&Inhabitant, &Parent)>,
+    buildings: &Query<(Entity, &Building, &GlobalTransform)>,
+) -> f32 {
+    inhabitants
+        .iter()
+        .filter_map(|(inhabitant, parent)| rent(inhabitant, parent.get(), buildings))
+        .sum()
+}
+
+/// Adds the report label, empty until the first quake is over
+fn add_default_entities(mut cmd: Commands, asset_server: Res<AssetServer>) {
+    cmd.spawn((
+        QuakeReportLabel,
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: asset_server.load("fonts/RobotoSlab.ttf"),
+                font_size: 26.0,
+                color: BLACK.into(),
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(130.0),
+            left: Val::Px(17.0),
+            ..default()
+        }),
+    ));
+
+    cmd.spawn((
+        QuakeReportLabel,
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: asset_server.load("fonts/RobotoSlab.ttf"),
+                font_size: 25.0,
+                color: WHITE.into(),
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(131.0),
+            left: Val::Px(20.0),
+            ..default()
+        }),
+    ));
+}
+
+/// Counts what breaks while the ground shakes and writes the report once it stops
+fn tally_damage(
+    mut tally: ResMut<DamageTally>,
+    mut history: ResMut<QuakeHistory>,
+    (mut joints, mut collapsed, mut killed): (
+        EventReader<JointBroken>,
+        EventReader<BuildingCollapsed>,
+        EventReader<InhabitantDied>,
+    ),
+    inhabitants: Query<(&Inhabitant, &Parent)>,
+    buildings: Query<(Entity, &Building, &GlobalTransform)>,
+    angles: Query<(Entity, &GlobalTransform), With<Building>>,
+    earthquake: Res<EarthquakeTimer>,
+) {
+    // read even between quakes, so old damage is not counted later
+    let joints_snapped = joints.read().count();
+    let buildings_lost = collapsed.read().count();
+    let inhabitants_killed = killed.read().count();
+
+    if earthquake.is_running() && !tally.running {
+        *tally = DamageTally {
+            running: true,
+            rent_before: total_rent(&inhabitants, &buildings),
+            angles_before: angles
+                .iter()
+                .map(|(entity, transform)| (entity, transform.right().xy()))
+                .collect(),
+            ..default()
+        };
+    }
+
+    if !tally.running {
+        return;
+    }
+
+    tally.joints_snapped += joints_snapped;
+    tally.buildings_lost += buildings_lost;
+    tally.inhabitants_killed += inhabitants_killed;
+
+    for (entity, transform) in &angles {
+        let before = match tally.angles_before.get(&entity) {
+            Some(x) => x,
+            None => continue,
+        };
+        let tilt = before.angle_between(transform.right().xy());
+        tally.peak_tilt = tally.peak_tilt.max(tilt.abs().to_degrees());
+    }
+
+    if earthquake.is_running() {
+        return;
+    }
+
+    tally.running = false;
+    history.reports.push(QuakeReport {
+        number: earthquake.count(),
+        magnitude: magnitude(earthquake.peak_displacement(), earthquake.peak_force()),
+        buildings_lost: tally.buildings_lost,
+        joints_snapped: tally.joints_snapped,
+        inhabitants_killed: tally.inhabitants_killed,
+        rent_lost: (tally.rent_before - total_rent(&inhabitants, &buildings)).max(0.0),
+        peak_tilt: tally.peak_tilt,
+    });
+    history.toast.reset();
+}
+
+/// Opens and closes the history
+fn toggle_history(keys: Res<ButtonInput<KeyCode>>, mut history: ResMut<QuakeHistory>) {
+    if keys.just_pressed(KeyCode::KeyL) {
+        history.open = !history.open;
+    }
+}
+
+/// Shows the last report for a while after each quake, or the whole history when opened
+fn update_report_label(
+    mut labels: Query<(&mut Text, &mut Visibility), With<QuakeReportLabel>>,
+    mut history: ResMut<QuakeHistory>,
+    time: Res<Time>,
+) {
+    history.toast.tick(time.delta());
+
+    let text = if history.open {
+        let lines: Vec<String> = history.reports.iter().map(QuakeReport::line).collect();
+        if lines.is_empty() {
+            "No earthquakes yet".to_string()
+        } else {
+            lines.join("\n")
+        }
+    } else {
+        match history.reports.last() {
+            Some(report) if !history.toast.finished() => report.summary(),
+            _ => "".to_string(),
+        }
+    };
+
+    for (mut label, mut visibility) in labels.iter_mut() {
+        *visibility = if text.is_empty() {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+        label.sections[0].value.clone_from(&text);
+    }
+}
+
+/// Plugin for the damage reports
+pub struct QuakeReportPlugin;
+
+impl Plugin for QuakeReportPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DamageTally>()
+            .insert_resource(QuakeHistory {
+                reports: vec![],
+                toast: Timer::from_seconds(REPORT_SECONDS, TimerMode::Once),
+                open: false,
+            })
+            .add_systems(Startup, add_default_entities)
+            .add_systems(
+                Update,
+                (tally_damage, toggle_history, update_report_label).chain(),
+            );
+    }
+}
@@ -0,0 +1,300 @@
+//! Seismograph panel plotting how much the plates move, live and for past quakes
+
+use std::collections::VecDeque;
+
+use avian2d::prelude::*;
+use bevy::{
+    color::palettes::css::{BLACK, DARK_GRAY, DEEP_SKY_BLUE, ORANGE, WHITE},
+    prelude::*,
+};
+
+use crate::earthquake::{average_displacement, EarthquakeTimer, Plate};
+
+/// Seconds the live plot reaches back
+const WINDOW_SECONDS: f32 = 20.0;
+/// Seconds between two samples
+const SAMPLE_SECONDS: f32 = 0.05;
+/// How many past quakes are shown below the live plot
+const PAST_QUAKES: usize = 3;
+/// Size of the live plot
+const PLOT_SIZE: Vec2 = Vec2::new(360.0, 110.0);
+/// Height of the plot of a past quake
+const PAST_PLOT_HEIGHT: f32 = 40.0;
+/// Space between the plots
+const PLOT_GAP: f32 = 8.0;
+/// Distance of the panel to the top right corner of the screen, below the earthquake countdown
+const PANEL_OFFSET: Vec2 = Vec2::new(20.0, 150.0);
+/// Average plate displacement in pixels which fills a plot
+const DISPLACEMENT_RANGE: f32 = 30.0;
+/// Average plate speed in pixels per second which fills a plot
+const VELOCITY_RANGE: f32 = 300.0;
+
+/// How much the plates moved at one point in time, averaged over all plates
+#[derive(Clone, Copy, Default)]
+struct Sample {
+    /// distance from where the plates rest
+    displacement: f32,
+    /// speed of the plates
+    velocity: f32,
+}
+
+/// The trace of a quake which is over, kept to compare with later ones
+struct PastQuake {
+    /// which quake it was
+    number: i32,
+    /// the samples while the ground was shaking
+    samples: Vec<Sample>,
+    /// how strong the quake was, see [`magnitude`]
+    magnitude: f32,
+}
+
+/// Everything the seismograph recorded
+#[derive(Resource)]
+struct Seismograph {
+    /// when the next sample is taken
+    timer: Timer,
+    /// samples of the rolling window, newest last
+    live: VecDeque<Sample>,
+    /// the quake which is shaking right now
+    current: Option<PastQuake>,
+    /// past quakes, newest first
+    past: VecDeque<PastQuake>,
+}
+
+/// Label of the live plot
+#[derive(Component)]
+struct SeismographLabel;
+
+/// Label of the plot of a past quake, newest is 0
+#[derive(Component)]
+struct PastQuakeLabel(usize);
+
+/// Magnitude from the strongest push on the plates and how far they moved.
+/// Like the moment magnitude it grows by 2/3 for every tenfold of that work.
+pub fn magnitude(peak_displacement: f32, peak_force: f32) -> f32 {
+    2.0 / 3.0 * (peak_displacement * peak_force).max(1.0).log10()
+}
+
+/// Distance from the top of the screen to the top of the plot of a past quake
+fn past_plot_top(slot: usize) -> f32 {
+    PANEL_OFFSET.y + PLOT_SIZE.y + PLOT_GAP + slot as f32 * (PAST_PLOT_HEIGHT + PLOT_GAP)
+}
+
+/// Adds the labels of the panel
+fn add_default_entities(mut cmd: Commands, asset_server: Res<AssetServer>) {
+    cmd.spawn((
+        SeismographLabel,
+        TextBundle::from_section(
+            "Seismograph",
+            TextStyle {
+                font: asset_server.load("fonts/RobotoSlab.ttf"),
+                font_size: 22.0,
+                color: BLACK.into(),
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(PANEL_OFFSET.y - 28.0),
+            right: Val::Px(PANEL_OFFSET.x - 2.0),
+            ..default()
+        }),
+    ));
+
+    cmd.spawn((
+        SeismographLabel,
+        TextBundle::from_section(
+            "Seismograph",
+            TextStyle {
+                font: asset_server.load("fonts/RobotoSlab.ttf"),
+                font_size: 20.0,
+                color: WHITE.into(),
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(PANEL_OFFSET.y - 27.0),
+            right: Val::Px(PANEL_OFFSET.x),
+            ..default()
+        }),
+    ));
+
+    for slot in 0..PAST_QUAKES {
+        cmd.spawn((
+            PastQuakeLabel(slot),
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/RobotoSlab.ttf"),
+                    font_size: 18.0,
+                    color: BLACK.into(),
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(past_plot_top(slot) + 1.0),
+                right: Val::Px(PANEL_OFFSET.x + 4.0),
+                ..default()
+            }),
+        ));
+
+        cmd.spawn((
+            PastQuakeLabel(slot),
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/RobotoSlab.ttf"),
+                    font_size: 17.0,
+                    color: WHITE.into(),
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(past_plot_top(slot) + 2.0),
+                right: Val::Px(PANEL_OFFSET.x + 6.0),
+                ..default()
+            }),
+        ));
+    }
+}
+
+/// Samples the average displacement and speed of the plates,
+/// the samples of a quake are kept once it is over
+fn record_plate_motion(
+    mut seismograph: ResMut<Seismograph>,
+    plates: Query<(&Plate, &Transform, &LinearVelocity)>,
+    earthquake: Res<EarthquakeTimer>,
+    time: Res<Time>,
+) {
+    seismograph.timer.tick(time.delta());
+    if !seismograph.timer.just_finished() || plates.is_empty() {
+        return;
+    }
+
+    let sample = Sample {
+        displacement: average_displacement(
+            plates
+                .iter()
+                .map(|(plate, transform, _)| (plate, transform)),
+        ),
+        velocity: plates
+            .iter()
+            .map(|(_, _, velocity)| velocity.length())
+            .sum::<f32>()
+            / plates.iter().len() as f32,
+    };
+
+    seismograph.live.push_back(sample);
+    while seismograph.live.len() > (WINDOW_SECONDS / SAMPLE_SECONDS) as usize {
+        seismograph.live.pop_front();
+    }
+
+    if earthquake.is_running() {
+        let current = seismograph.current.get_or_insert_with(|| PastQuake {
+            number: earthquake.count(),
+            samples: vec![],
+            magnitude: 0.0,
+        });
+        current.samples.push(sample);
+        current.magnitude = magnitude(earthquake.peak_displacement(), earthquake.peak_force());
+    } else if let Some(quake) = seismograph.current.take() {
+        seismograph.past.push_front(quake);
+        seismograph.past.truncate(PAST_QUAKES);
+    }
+}
+
+/// Draws the samples as lines into a box, displacement and speed in their own colors
+fn draw_plot(gizmos: &mut Gizmos, samples: &[Sample], capacity: usize, min: Vec2, size: Vec2) {
+    gizmos.rect_2d(min + size / 2.0, 0.0, size, DARK_GRAY);
+
+    let step = size.x / capacity.max(2) as f32;
+    let point = |i: usize, value: f32, range: f32| {
+        min + Vec2::new(i as f32 * step, (value / range).min(1.0) * size.y)
+    };
+
+    for (i, pair) in samples.windows(2).enumerate() {
+        gizmos.line_2d(
+            point(i, pair[0].velocity, VELOCITY_RANGE),
+            point(i + 1, pair[1].velocity, VELOCITY_RANGE),
+            DEEP_SKY_BLUE,
+        );
+        gizmos.line_2d(
+            point(i, pair[0].displacement, DISPLACEMENT_RANGE),
+            point(i + 1, pair[1].displacement, DISPLACEMENT_RANGE),
+            ORANGE,
+        );
+    }
+}
+
+/// Draws the live plot and the past quakes in the top right corner of the screen
+fn draw_seismograph(
+    seismograph: Res<Seismograph>,
+    windows: Query<&Window>,
+    cameras: Query<&Transform, With<Camera>>,
+    mut gizmos: Gizmos,
+) {
+    if cameras.is_empty() {
+        return;
+    }
+
+    let window = windows.single();
+    let camera = cameras.single().translation.xy();
+
+    // the panel sticks to the screen, not the world
+    let top_right = camera + window.size() / 2.0;
+    let left = top_right.x - PANEL_OFFSET.x - PLOT_SIZE.x;
+
+    let live: Vec<Sample> = seismograph.live.iter().copied().collect();
+    draw_plot(
+        &mut gizmos,
+        &live,
+        (WINDOW_SECONDS / SAMPLE_SECONDS) as usize,
+        Vec2::new(left, top_right.y - PANEL_OFFSET.y - PLOT_SIZE.y),
+        PLOT_SIZE,
+    );
+
+    for (slot, quake) in seismograph.past.iter().enumerate() {
+        draw_plot(
+            &mut gizmos,
+            &quake.samples,
+            quake.samples.len(),
+            Vec2::new(left, top_right.y - past_plot_top(slot) - PAST_PLOT_HEIGHT),
+            Vec2::new(PLOT_SIZE.x, PAST_PLOT_HEIGHT),
+        );
+    }
+}
+
+/// Shows the peak magnitude of the past quakes
+fn update_past_quake_labels(
+    mut labels: Query<(&mut Text, &PastQuakeLabel)>,
+    seismograph: Res<Seismograph>,
+) {
+    for (mut label, slot) in labels.iter_mut() {
+        label.sections[0].value = match seismograph.past.get(slot.0) {
+            Some(quake) => format!("#{} M {:.1}", quake.number, quake.magnitude),
+            None => "".to_string(),
+        };
+    }
+}
+
+/// Plugin for the seismograph panel
+pub struct SeismographPlugin;
+
+impl Plugin for SeismographPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Seismograph {
+            timer: Timer::from_seconds(SAMPLE_SECONDS, TimerMode::Repeating),
+            live: VecDeque::new(),
+            current: None,
+            past: VecDeque::new(),
+        })
+        .add_systems(Startup, add_default_entities)
+        .add_systems(
+            Update,
+            (
+                record_plate_motion,
+                draw_seismograph,
+                update_past_quake_labels,
+            ),
+        );
+    }
+}